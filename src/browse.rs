@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+use std::future::Future;
+
+use anyhow::Result;
+use dioxus::prelude::*;
+
+use crate::{current_features, get_schema, get_sub_tables, get_tags, search_sub_tables, td};
+
+// 按需查询的数据的加载状态
+#[derive(Clone, PartialEq)]
+pub enum Load<T> {
+    Loading,
+    Loaded(T),
+    Failed(String),
+}
+
+impl<T> From<Result<T>> for Load<T> {
+    fn from(result: Result<T>) -> Self {
+        match result {
+            Ok(value) => Load::Loaded(value),
+            Err(err) => Load::Failed(format!("{:#}", err)),
+        }
+    }
+}
+
+// 缓存的键: 主机、数据库和超表 (或搜索词)
+pub type CacheKey = (String, String, String);
+
+// 按键缓存的加载结果, 同时记录加载时的刷新次数
+pub type Cache<T> = Signal<HashMap<CacheKey, (u64, Load<T>)>>;
+
+// 挂载和每次刷新时加载, 缓存是本次刷新之后加载完成的就直接使用
+// 卸载时加载任务随之结束, 所以 Loading 也需要重新加载
+fn use_cached<T, F, Fut>(mut cache: Cache<T>, key: CacheKey, refresh: Signal<u64>, load: F) -> Load<T>
+where
+    T: Clone + 'static,
+    F: Fn(CacheKey) -> Fut + 'static,
+    Fut: Future<Output = Result<T>> + 'static,
+{
+    use_effect({
+        let key = key.clone();
+        move || {
+            let generation = refresh();
+            let cached = cache
+                .peek()
+                .get(&key)
+                .is_some_and(|(loaded, state)| *loaded == generation && matches!(state, Load::Loaded(_)));
+            if cached {
+                return;
+            }
+            cache.write().insert(key.clone(), (generation, Load::Loading));
+            let task = load(key.clone());
+            let key = key.clone();
+            spawn(async move {
+                let state = Load::from(task.await);
+                cache.write().insert(key, (generation, state));
+            });
+        }
+    });
+    let state = cache.read().get(&key).map(|(_, state)| state.clone());
+    state.unwrap_or(Load::Loading)
+}
+
+// 展开的超表和搜索结果最多显示的子表数, 查询时多取一个用于判断是否还有更多
+static SUB_TABLE_LIMIT: usize = 200;
+
+// 子表列表项的样式, 当前打开的表加上左边框
+fn sub_table_class(table: &str, current: &str) -> &'static str {
+    if table == current {
+        "border-b cursor-pointer hover:bg-gray-200 p-2 border-l-4 border-sky-500 text-gray-500 text-sm pl-8"
+    } else {
+        "border-b cursor-pointer hover:bg-gray-200 p-2 text-gray-500 text-sm pl-8"
+    }
+}
+
+#[derive(Props, PartialEq, Clone)]
+pub struct SubTableItems {
+    pub stable: CacheKey,
+    pub sub_tables: Cache<Vec<String>>,
+    pub refresh: Signal<u64>,
+    pub current: String,
+    pub on_stable_change: EventHandler<String>,
+}
+
+#[allow(non_snake_case)]
+#[component]
+pub fn SubTableList(props: SubTableItems) -> Element {
+    let state = use_cached(props.sub_tables, props.stable.clone(), props.refresh, |(name, db, stable)| {
+        get_sub_tables(name, db, stable, SUB_TABLE_LIMIT + 1)
+    });
+    match state {
+        Load::Loading => rsx! {
+            p { class: "text-gray-400 text-sm pl-8 py-1", "加载中..." }
+        },
+        Load::Failed(err) => rsx! {
+            p { class: "text-red-500 text-sm pl-8 py-1", "加载子表失败: {err}" }
+        },
+        Load::Loaded(tables) => rsx! {
+            for table in tables.iter().take(SUB_TABLE_LIMIT) {
+                li {
+                    class: sub_table_class(table, &props.current),
+                    onclick: {
+                        let on_stable_change = props.on_stable_change.clone();
+                        let table = table.clone();
+                        move |_evt| {
+                            on_stable_change.call(table.clone());
+                        }
+                    },
+                    "{table}"
+                }
+            }
+            if tables.len() > SUB_TABLE_LIMIT {
+                p {
+                    class: "text-gray-400 text-sm pl-8 py-1",
+                    "只显示前 {SUB_TABLE_LIMIT} 个子表, 输入名称搜索其余子表"
+                }
+            }
+        },
+    }
+}
+
+#[derive(Props, PartialEq, Clone)]
+pub struct SubTableMatches {
+    pub search: CacheKey,
+    pub found: Cache<Vec<(String, String)>>,
+    pub refresh: Signal<u64>,
+    pub current: String,
+    pub on_stable_change: EventHandler<String>,
+}
+
+#[allow(non_snake_case)]
+#[component]
+pub fn SubTableSearch(props: SubTableMatches) -> Element {
+    // 与展开的子表相同, 挂载和每次刷新时搜索
+    let state = use_cached(props.found, props.search.clone(), props.refresh, |(name, db, keyword)| {
+        search_sub_tables(name, db, keyword, SUB_TABLE_LIMIT + 1)
+    });
+    rsx! {
+        div {
+            class: "list-none border mt-1",
+            p { class: "text-gray-400 text-sm p-1", "名称包含 \"{props.search.2}\" 的子表" }
+            match state {
+                Load::Loading => rsx! {
+                    p { class: "text-gray-400 text-sm pl-8 py-1", "搜索中..." }
+                },
+                Load::Failed(err) => rsx! {
+                    p { class: "text-red-500 text-sm pl-8 py-1", "搜索子表失败: {err}" }
+                },
+                Load::Loaded(tables) => rsx! {
+                    if tables.is_empty() {
+                        p { class: "text-gray-400 text-sm pl-8 py-1", "没有匹配的子表" }
+                    }
+                    for (stable, table) in tables.iter().take(SUB_TABLE_LIMIT) {
+                        li {
+                            class: sub_table_class(table, &props.current),
+                            onclick: {
+                                let on_stable_change = props.on_stable_change.clone();
+                                let table = table.clone();
+                                move |_evt| {
+                                    on_stable_change.call(table.clone());
+                                }
+                            },
+                            "{table}"
+                            span { class: "text-gray-400 ml-2", "{stable}" }
+                        }
+                    }
+                    if tables.len() > SUB_TABLE_LIMIT {
+                        p {
+                            class: "text-gray-400 text-sm pl-8 py-1",
+                            "只显示前 {SUB_TABLE_LIMIT} 个匹配的子表, 请输入更完整的名称"
+                        }
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[derive(Props, PartialEq, Clone)]
+pub struct TagGridList {
+    pub table: CacheKey,
+    pub tags: Cache<td::TagTable>,
+    pub refresh: Signal<u64>,
+    pub filters: Vec<td::TagFilter>,
+    pub on_filter: EventHandler<Vec<td::TagFilter>>,
+    pub on_open: EventHandler<String>,
+}
+
+#[allow(non_snake_case)]
+#[component]
+pub fn TagGrid(props: TagGridList) -> Element {
+    let state = use_cached(props.tags, props.table.clone(), props.refresh, |(name, db, stable)| {
+        get_tags(name, db, stable)
+    });
+    // 点击标签值时加入或移除该值
+    let toggle = {
+        let filters = props.filters.clone();
+        move |tag: td::Column, value: String| {
+            let mut filters = filters.clone();
+            match filters.iter().position(|filter| filter.tag == tag) {
+                Some(index) => {
+                    let values = &mut filters[index].values;
+                    match values.iter().position(|v| *v == value) {
+                        Some(position) => {
+                            values.remove(position);
+                        }
+                        None => values.push(value),
+                    }
+                    if values.is_empty() {
+                        filters.remove(index);
+                    }
+                }
+                None => filters.push(td::TagFilter { tag, values: vec![value] }),
+            }
+            filters
+        }
+    };
+    let selected = |tag: &td::Column, value: &str| {
+        props
+            .filters
+            .iter()
+            .any(|filter| filter.tag == *tag && filter.values.iter().any(|v| v == value))
+    };
+    // 只列出满足过滤条件的子表
+    let visible = |table: &td::TagTable| {
+        table
+            .tables
+            .iter()
+            .filter(|(_, values)| {
+                props.filters.iter().all(|filter| {
+                    table
+                        .tags
+                        .iter()
+                        .position(|tag| *tag == filter.tag)
+                        .is_some_and(|index| filter.values.contains(&values[index]))
+                })
+            })
+            .cloned()
+            .collect::<Vec<(String, Vec<String>)>>()
+    };
+    rsx! {
+            match state {
+                Load::Loading => rsx! {
+                    p { class: "text-gray-400 p-1", "加载中..." }
+                },
+                Load::Failed(err) => rsx! {
+                    p { class: "text-red-500 p-1", "加载标签失败: {err}" }
+                },
+                Load::Loaded(table) if table.tags.is_empty() => rsx! {
+                    p { class: "text-gray-400 p-1", "该超表没有标签" }
+                },
+                Load::Loaded(table) => rsx! {
+                    div {
+                        class: "overflow-auto border mt-1",
+                        style: "max-height:300px",
+                        table {
+                            class: "table-auto border-collapse w-full text-sm",
+                            thead {
+                                tr {
+                                    th { class: "border border-slate-300 text-left px-1", "子表" }
+                                    for tag in table.tags.iter() {
+                                        th { class: "border border-slate-300 text-left px-1", "{tag.name} ({tag.kind})" }
+                                    }
+                                }
+                            }
+                            tbody {
+                                for (name, values) in visible(&table).into_iter().take(SUB_TABLE_LIMIT) {
+                                    tr {
+                                        td {
+                                            class: "border border-slate-300 px-1 cursor-pointer text-sky-700 hover:bg-gray-200",
+                                            onclick: {
+                                                let on_open = props.on_open.clone();
+                                                let name = name.clone();
+                                                move |_| on_open.call(name.clone())
+                                            },
+                                            "{name}"
+                                        }
+                                        for (tag, value) in table.tags.iter().zip(values.iter()) {
+                                            td {
+                                                class: if selected(tag, value) {
+                                                    "border border-slate-300 px-1 cursor-pointer bg-sky-100"
+                                                } else {
+                                                    "border border-slate-300 px-1 cursor-pointer hover:bg-gray-200"
+                                                },
+                                                onclick: {
+                                                    let on_filter = props.on_filter.clone();
+                                                    let toggle = toggle.clone();
+                                                    let tag = tag.clone();
+                                                    let value = value.clone();
+                                                    move |_| on_filter.call(toggle(tag.clone(), value.clone()))
+                                                },
+                                                "{value}"
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    if visible(&table).len() > SUB_TABLE_LIMIT {
+                        p {
+                            class: "text-gray-400 text-sm p-1",
+                            "只显示前 {SUB_TABLE_LIMIT} 个子表, 点击标签值缩小范围"
+                        }
+                    }
+                },
+            }
+    }
+}
+
+#[derive(Props, PartialEq, Clone)]
+pub struct SchemaList {
+    pub current_host: String,
+    pub current_db: String,
+    pub table: String,
+    pub stable: bool,
+}
+
+#[allow(non_snake_case)]
+#[component]
+pub fn SchemaPane(props: SchemaList) -> Element {
+    // 组件按表挂载, 挂载时查询一次
+    let schema = use_resource({
+        let key = (props.current_host.clone(), props.current_db.clone(), props.table.clone(), props.stable);
+        move || {
+            let (name, db, table, stable) = key.clone();
+            async move { Load::from(get_schema(name, db, table, stable).await) }
+        }
+    });
+    let state = schema.value().read().clone().unwrap_or(Load::Loading);
+    // 2.x 没有表注释, 不显示注释一栏
+    let table_comment = current_features().table_comment;
+    let cell = "border border-slate-300 px-1 text-left whitespace-nowrap";
+    rsx! {
+        div {
+            class: "ml-2 border rounded-md p-2 overflow-auto text-sm text-gray-600",
+            style: "width:480px;max-height:1000px",
+            p { class: "font-bold mb-1", "{props.current_db}.{props.table}" }
+            match state {
+                Load::Loading => rsx! {
+                    p { class: "text-gray-400", "加载中..." }
+                },
+                Load::Failed(err) => rsx! {
+                    p { class: "text-red-500", "加载表结构失败: {err}" }
+                },
+                Load::Loaded(schema) => rsx! {
+                    if table_comment {
+                        p { class: "text-gray-500 mb-1", "注释: {schema.comment.as_deref().unwrap_or(\"无\")}" }
+                    }
+                    for (title, rows) in [("列", schema.columns.clone()), ("标签", schema.tags.clone())] {
+                        if !rows.is_empty() {
+                            p { class: "font-bold mt-2", "{title}" }
+                            table {
+                                class: "border-collapse w-full",
+                                thead {
+                                    tr {
+                                        for header in schema.headers.iter() {
+                                            th { class: "{cell} bg-sky-500 text-white", "{header}" }
+                                        }
+                                    }
+                                }
+                                tbody {
+                                    for row in rows.iter() {
+                                        tr {
+                                            for value in row.iter() {
+                                                td { class: "{cell}", "{value}" }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    div {
+                        class: "flex items-center mt-2",
+                        p { class: "font-bold", "建表语句" }
+                        button {
+                            class: "ml-auto bg-sky-300 hover:bg-sky-500 text-white px-2 rounded",
+                            onclick: {
+                                let ddl = schema.ddl.clone();
+                                move |_| {
+                                    let text = serde_json::to_string(&ddl).unwrap_or_default();
+                                    eval(&format!("navigator.clipboard.writeText({})", text));
+                                }
+                            },
+                            "复制"
+                        }
+                    }
+                    pre {
+                        class: "whitespace-pre-wrap border border-slate-300 rounded-md p-1 select-all",
+                        "{schema.ddl}"
+                    }
+                },
+            }
+        }
+    }
+}
@@ -1,29 +1,916 @@
-use std::fs::File;
-use std::io::Read;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
+use anyhow::{bail, Context, Result};
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
+use toml_edit::{ArrayOfTables, Document, Item, Table, Value};
+
+use crate::proxy::Proxy;
+use crate::td::TimeDisplay;
+use crate::vault;
+
+// 指定配置文件的环境变量
+pub const CONFIG_ENV: &str = "TDENGINE_DESKTOP_CONFIG";
 
 lazy_static! {
-    pub static ref CONF: Config = {
-        let mut file = File::open("/tmp/config.toml").expect("invaid file path");
-        let mut content = String::new();
-        file.read_to_string(&mut content)
-            .expect("read config failed");
-        toml::from_str::<Config>(content.as_str()).unwrap()
-    };
+    pub static ref CONF: Mutex<Config> = Mutex::new(Config::default());
+    pub static ref CONF_PATH: Mutex<PathBuf> = Mutex::new(PathBuf::new());
+    // 本程序最后一次写入配置文件的内容, 监听时跳过自己的保存
+    static ref WRITTEN: Mutex<String> = Mutex::new(String::new());
 }
-#[derive(Serialize, Deserialize)]
+
+// 保存配置会改写 WRITTEN, 写配置文件的测试需要串行执行
+#[cfg(test)]
+pub static SAVE_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
 pub struct Config {
+    // 密钥库文件, 相对路径基于配置文件所在目录, 默认为 vault.bin
+    pub vault: Option<PathBuf>,
     pub sources: Vec<Source>,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Default)]
 pub struct Source {
+    pub name: Option<String>,
     pub ip: String,
+    // TDengine 端口, 必填, 直连和 ssh 转发都使用该端口. 原生连接一般为 6030, taosAdapter 一般为 6041
     pub port: usize,
+    // 连接方式, 默认为原生连接
+    pub protocol: Option<Protocol>,
+    // wss 连接 taosAdapter 时的 token
+    pub token: Option<Secret>,
+    // wss 额外信任的 CA 证书 (PEM), 用于自签名的 taosAdapter
+    pub ca_path: Option<PathBuf>,
+    // wss 不校验服务端证书, 只用于测试环境
+    pub insecure: Option<bool>,
     pub ssh_user: Option<String>,
-    pub ssh_password: Option<String>,
+    // ssh 端口, 默认 22
+    pub ssh_port: Option<usize>,
+    // ssh 认证: 配置了密码用密码, 配置了私钥用私钥, 都没有时使用 ssh-agent
+    pub ssh_password: Option<Secret>,
+    pub ssh_key: Option<PathBuf>,
+    pub ssh_key_passphrase: Option<Secret>,
+    // 依次经过的跳板机, 最后一跳再连接到 ip 上的 ssh
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub jump: Vec<JumpHost>,
+    // ssh 转发的本地端口, 不配置时自动分配
     pub local_port: Option<usize>,
+    // 代理地址 socks5://[user@]host:port 或 http://[user@]host:port, ssh 和直连都经过代理
+    pub proxy: Option<String>,
+    pub proxy_password: Option<Secret>,
     pub db: String,
+    // TDengine 用户名和密码, 不配置时使用服务端默认账号
+    pub user: Option<String>,
+    pub password: Option<Secret>,
+    // 查询超时秒数, 超时后在服务端终止查询, 不配置时不限制
+    pub query_timeout: Option<u64>,
+    // 时间戳显示的时区, local、Asia/Shanghai 等 IANA 时区名、UTC 或 +08:00, 默认 local
+    pub timezone: Option<String>,
+    // 决定日期的写法, 如 zh_CN、en_US, 默认 zh_CN
+    pub locale: Option<String>,
+    // strftime 格式, 不配置时按数据库精度显示到毫秒、微秒或纳秒
+    pub timestamp_format: Option<String>,
+}
+
+// native 需要本机安装 TDengine 客户端, ws/wss 经 taosAdapter 连接, 无需客户端
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    #[default]
+    Native,
+    Ws,
+    Wss,
+}
+
+// ssh 跳板机, 认证方式与数据源的 ssh 相同
+#[derive(Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct JumpHost {
+    pub ip: String,
+    pub port: Option<usize>,
+    pub user: String,
+    pub password: Option<Secret>,
+    pub key: Option<PathBuf>,
+    pub key_passphrase: Option<Secret>,
+}
+
+// 密码可以写明文, 也可以写 { vault = "名称" } 引用密钥库中的条目
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Secret {
+    Vault { vault: String },
+    Plain(String),
+}
+
+// 配置中的一个明文密码, name 为迁移后在密钥库中的名称
+#[derive(Clone, PartialEq)]
+pub struct PlainSecret {
+    pub index: usize,
+    pub label: String,
+    pub field: String,
+    pub name: String,
+}
+
+// Source 中由程序维护的键, 写回配置时其余键原样保留
+const SOURCE_KEYS: &[&str] = &[
+    "name",
+    "ip",
+    "port",
+    "protocol",
+    "token",
+    "ca_path",
+    "insecure",
+    "ssh_user",
+    "ssh_port",
+    "ssh_password",
+    "ssh_key",
+    "ssh_key_passphrase",
+    "jump",
+    "local_port",
+    "proxy",
+    "proxy_password",
+    "db",
+    "user",
+    "password",
+    "query_timeout",
+    "timezone",
+    "locale",
+    "timestamp_format",
+];
+
+// 连接管理器中的一条数据源, origin 为其在配置文件 sources 中的原始下标
+#[derive(Clone, PartialEq)]
+pub struct SourceEntry {
+    pub origin: Option<usize>,
+    pub source: Source,
+}
+
+// 配置文件查找顺序: --config 参数 > TDENGINE_DESKTOP_CONFIG 环境变量 > XDG 配置目录
+pub fn config_path() -> Result<PathBuf> {
+    resolve_path(std::env::args().skip(1), |key| std::env::var_os(key))
+}
+
+fn resolve_path(mut args: impl Iterator<Item = String>, var: impl Fn(&str) -> Option<OsString>) -> Result<PathBuf> {
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().map(PathBuf::from).context("--config requires a path");
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Ok(PathBuf::from(path));
+        }
+    }
+
+    if let Some(path) = var(CONFIG_ENV) {
+        if !path.is_empty() {
+            return Ok(PathBuf::from(path));
+        }
+    }
+
+    let config_dir = match var("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => var("HOME")
+            .map(|home| PathBuf::from(home).join(".config"))
+            .context("neither XDG_CONFIG_HOME nor HOME is set")?,
+    };
+    Ok(config_dir.join("tdengine-desktop").join("config.toml"))
+}
+
+// 读取并校验配置文件
+pub fn load() -> Result<(PathBuf, Config)> {
+    let path = config_path()?;
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("failed to read config file {}", path.display()))?;
+    let config = parse(&path, &content)?;
+
+    Ok((path, config))
+}
+
+fn parse(path: &Path, content: &str) -> Result<Config> {
+    let config = toml::from_str::<Config>(content)
+        .with_context(|| format!("invalid config file {}", path.display()))?;
+    config
+        .validate()
+        .with_context(|| format!("invalid config file {}", path.display()))?;
+    Ok(config)
+}
+
+// 监听配置文件: 定时读取, 内容变化时重新解析.
+// 编辑器保存时可能短暂删除文件, 读取失败时视为未变化; 内容是本程序刚写入的也视为未变化
+pub struct Watcher {
+    path: PathBuf,
+    content: String,
+}
+
+impl Watcher {
+    pub fn new(path: PathBuf) -> Watcher {
+        let content = fs::read_to_string(&path).unwrap_or_default();
+        Watcher { path, content }
+    }
+
+    pub fn poll(&mut self) -> Option<Result<Config>> {
+        let content = fs::read_to_string(&self.path).ok()?;
+        if content == self.content {
+            return None;
+        }
+        self.content = content;
+        if *WRITTEN.lock().unwrap() == self.content {
+            return None;
+        }
+        Some(parse(&self.path, &self.content))
+    }
+}
+
+// 两份配置之间按名称比较出的数据源变化
+#[derive(Default)]
+pub struct ConfigDiff {
+    pub added: Vec<Source>,
+    pub removed: Vec<String>,
+    pub changed: Vec<Source>,
+}
+
+impl ConfigDiff {
+    // 该主机是否需要断开或重连
+    pub fn affects(&self, name: &str) -> bool {
+        self.removed.iter().any(|removed| removed == name)
+            || self.changed.iter().any(|source| source.label() == name)
+    }
+}
+
+pub fn diff(old: &[Source], new: &[Source]) -> ConfigDiff {
+    let mut diff = ConfigDiff::default();
+    for source in new.iter() {
+        match old.iter().find(|old| old.label() == source.label()) {
+            None => diff.added.push(source.clone()),
+            Some(old) if old != source => diff.changed.push(source.clone()),
+            _ => {}
+        }
+    }
+    for source in old.iter() {
+        if !new.iter().any(|new| new.label() == source.label()) {
+            diff.removed.push(source.label());
+        }
+    }
+    diff
+}
+
+// 将数据源写回配置文件, 保留注释和未知的键
+pub fn save(path: &Path, entries: &[SourceEntry]) -> Result<()> {
+    let content = fs::read_to_string(path).unwrap_or_default();
+    let mut doc = content
+        .parse::<Document>()
+        .with_context(|| format!("invalid config file {}", path.display()))?;
+    let origin_tables = doc
+        .get("sources")
+        .and_then(|item| item.as_array_of_tables())
+        .cloned()
+        .unwrap_or_default();
+    let position = origin_tables.iter().filter_map(|table| table.position()).min();
+
+    let mut tables = ArrayOfTables::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let mut table = entry
+            .origin
+            .and_then(|origin| origin_tables.get(origin))
+            .cloned()
+            .unwrap_or_default();
+        // 复制出来的数据源不再重复原表上方的注释
+        if entries[..index].iter().any(|e| e.origin.is_some() && e.origin == entry.origin) {
+            table.decor_mut().clear();
+        }
+        entry.source.write_to(&mut table)?;
+        // 输出时按表的位置排序, 统一位置才能保持列表中的顺序
+        if let Some(position) = position {
+            set_position(&mut table, position);
+        }
+        tables.push(table);
+    }
+    doc.insert("sources", Item::ArrayOfTables(tables));
+
+    // 先写临时文件再替换, 避免写到一半的配置
+    let content = doc.to_string();
+    let tmp = path.with_extension("toml.tmp");
+    fs::write(&tmp, &content)
+        .with_context(|| format!("failed to write config file {}", tmp.display()))?;
+    // 替换前记下内容, 监听线程读到新文件时已能识别
+    *WRITTEN.lock().unwrap() = content;
+    fs::rename(&tmp, path)
+        .with_context(|| format!("failed to write config file {}", path.display()))?;
+    Ok(())
+}
+
+fn set_position(table: &mut Table, position: usize) {
+    table.set_position(position);
+    for (_, item) in table.iter_mut() {
+        match item {
+            Item::Table(table) => set_position(table, position),
+            Item::ArrayOfTables(tables) => {
+                for table in tables.iter_mut() {
+                    set_position(table, position);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn inline_tables(table: &mut Table) {
+    let keys = table
+        .iter()
+        .filter(|(_, item)| item.is_table())
+        .map(|(key, _)| key.to_string())
+        .collect::<Vec<String>>();
+    for key in keys {
+        if let Some(Item::Table(sub)) = table.remove(&key) {
+            table.insert(&key, Item::Value(Value::InlineTable(sub.into_inline_table())));
+        }
+    }
+}
+
+impl Secret {
+    pub fn resolve(&self) -> Result<String> {
+        match self {
+            Secret::Vault { vault } => vault::get(vault),
+            Secret::Plain(value) => Ok(value.clone()),
+        }
+    }
+
+    // 编辑框中用 vault:名称 表示引用密钥库
+    pub fn from_text(text: String) -> Option<Secret> {
+        if text.is_empty() {
+            None
+        } else if let Some(name) = text.strip_prefix("vault:") {
+            Some(Secret::Vault {
+                vault: name.trim().to_string(),
+            })
+        } else {
+            Some(Secret::Plain(text))
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        match self {
+            Secret::Vault { vault } => format!("vault:{}", vault),
+            Secret::Plain(value) => value.clone(),
+        }
+    }
+}
+
+impl Config {
+    pub fn vault_path(&self) -> PathBuf {
+        let dir = CONF_PATH
+            .lock()
+            .unwrap()
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        match &self.vault {
+            Some(path) => dir.join(path),
+            None => dir.join("vault.bin"),
+        }
+    }
+
+    // 是否有数据源引用了密钥库
+    pub fn uses_vault(&self) -> bool {
+        self.sources.iter().any(|source| {
+            source
+                .secrets()
+                .iter()
+                .any(|(_, secret)| matches!(secret, Some(Secret::Vault { .. })))
+        })
+    }
+
+    pub fn plaintext_secrets(&self) -> Vec<PlainSecret> {
+        let mut secrets = Vec::new();
+        for (index, source) in self.sources.iter().enumerate() {
+            for (field, secret) in source.secrets() {
+                if let Some(Secret::Plain(_)) = secret {
+                    secrets.push(PlainSecret {
+                        index,
+                        label: source.label(),
+                        name: format!("{}.{}", source.label(), field),
+                        field,
+                    });
+                }
+            }
+        }
+        secrets
+    }
+
+    // 校验所有数据源, 一次性返回全部错误
+    pub fn validate(&self) -> Result<()> {
+        let mut errors = Vec::new();
+        if self.sources.is_empty() {
+            errors.push("no [[sources]] configured".to_string());
+        }
+
+        for (index, source) in self.sources.iter().enumerate() {
+            for error in source.validate() {
+                errors.push(format!("sources[{}] ({}): {}", index, source.label(), error));
+            }
+            // 切换主机时按名称查找, 不允许重复
+            if self.sources[..index].iter().any(|s| s.label() == source.label()) {
+                errors.push(format!(
+                    "sources[{}] ({}): duplicate name, set a distinct name",
+                    index,
+                    source.label()
+                ));
+            }
+            // 同一本地端口只能转发一个数据源
+            if let Some(port) = source.local_port {
+                if let Some(other) = self.sources[..index].iter().position(|s| s.local_port == Some(port)) {
+                    errors.push(format!(
+                        "sources[{}] ({}): local_port {} is already used by sources[{}] ({})",
+                        index,
+                        source.label(),
+                        port,
+                        other,
+                        self.sources[other].label()
+                    ));
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            bail!(errors.join("\n"));
+        }
+        Ok(())
+    }
+}
+
+impl Source {
+    // 主机标识, 未配置 name 时使用 ip
+    pub fn label(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.ip.clone())
+    }
+
+    // 所有密码字段, 跳板机的字段名形如 jump[0].password
+    pub fn secrets(&self) -> Vec<(String, &Option<Secret>)> {
+        let mut secrets = vec![
+            ("ssh_password".to_string(), &self.ssh_password),
+            ("ssh_key_passphrase".to_string(), &self.ssh_key_passphrase),
+            ("password".to_string(), &self.password),
+            ("token".to_string(), &self.token),
+            ("proxy_password".to_string(), &self.proxy_password),
+        ];
+        for (index, jump) in self.jump.iter().enumerate() {
+            secrets.push((format!("jump[{}].password", index), &jump.password));
+            secrets.push((format!("jump[{}].key_passphrase", index), &jump.key_passphrase));
+        }
+        secrets
+    }
+
+    pub fn secret_mut(&mut self, field: &str) -> Option<&mut Option<Secret>> {
+        match field {
+            "ssh_password" => return Some(&mut self.ssh_password),
+            "ssh_key_passphrase" => return Some(&mut self.ssh_key_passphrase),
+            "password" => return Some(&mut self.password),
+            "token" => return Some(&mut self.token),
+            "proxy_password" => return Some(&mut self.proxy_password),
+            _ => {}
+        }
+        let (index, field) = field.strip_prefix("jump[")?.split_once("].")?;
+        let jump = self.jump.get_mut(index.parse::<usize>().ok()?)?;
+        match field {
+            "password" => Some(&mut jump.password),
+            "key_passphrase" => Some(&mut jump.key_passphrase),
+            _ => None,
+        }
+    }
+
+    pub fn time_display(&self) -> Result<TimeDisplay> {
+        TimeDisplay::new(
+            self.timezone.as_deref(),
+            self.locale.as_deref(),
+            self.timestamp_format.as_deref(),
+        )
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        for (field, secret) in self.secrets() {
+            if let Some(Secret::Vault { vault }) = secret {
+                if vault.trim().is_empty() {
+                    errors.push(format!("{} refers to an empty vault name", field));
+                }
+            }
+        }
+        if let Some(name) = &self.name {
+            if name.trim().is_empty() {
+                errors.push("name must not be empty".to_string());
+            }
+        }
+        if self.ip.trim().is_empty() {
+            errors.push("ip must not be empty".to_string());
+        } else if self.ip.contains(char::is_whitespace) {
+            errors.push("ip must not contain whitespace".to_string());
+        }
+        if !(1..=65535).contains(&self.port) {
+            errors.push(format!("port {} is out of range 1-65535", self.port));
+        }
+        if self.db.trim().is_empty() {
+            errors.push("db must not be empty".to_string());
+        }
+        match self.protocol.unwrap_or_default() {
+            Protocol::Native if self.token.is_some() => {
+                errors.push("token requires protocol ws or wss".to_string())
+            }
+            // 经隧道连接时地址是 127.0.0.1, 证书无法匹配
+            Protocol::Wss if self.ssh_user.is_some() => {
+                errors.push("protocol wss cannot be used through an ssh tunnel, use ws".to_string())
+            }
+            _ => {}
+        }
+        let insecure = self.insecure.unwrap_or_default();
+        if self.protocol.unwrap_or_default() != Protocol::Wss {
+            if self.ca_path.is_some() {
+                errors.push("ca_path requires protocol wss".to_string());
+            }
+            if insecure {
+                errors.push("insecure requires protocol wss".to_string());
+            }
+        } else if let Some(path) = &self.ca_path {
+            if insecure {
+                errors.push("set either ca_path or insecure, not both".to_string());
+            }
+            if !path.is_file() {
+                errors.push(format!("ca_path {} does not exist", path.display()));
+            }
+        }
+        match &self.proxy {
+            Some(proxy) => {
+                if let Err(err) = Proxy::parse(proxy) {
+                    errors.push(format!("proxy: {:#}", err));
+                }
+            }
+            None if self.proxy_password.is_some() => {
+                errors.push("proxy_password is set but proxy is missing".to_string())
+            }
+            None => {}
+        }
+        match &self.user {
+            Some(user) if user.trim().is_empty() => {
+                errors.push("user must not be empty".to_string())
+            }
+            None if self.password.is_some() => {
+                errors.push("password is set but user is missing".to_string())
+            }
+            _ => {}
+        }
+        if self.query_timeout == Some(0) {
+            errors.push("query_timeout must be greater than 0".to_string());
+        }
+        if let Err(err) = self.time_display() {
+            errors.push(format!("{:#}", err));
+        }
+
+        match &self.ssh_user {
+            Some(user) => {
+                if user.trim().is_empty() {
+                    errors.push("ssh_user must not be empty".to_string());
+                }
+                if let Some(port) = self.local_port {
+                    if !(1..=65535).contains(&port) {
+                        errors.push(format!("local_port {} is out of range 1-65535", port));
+                    }
+                }
+                if let Some(port) = self.ssh_port {
+                    if !(1..=65535).contains(&port) {
+                        errors.push(format!("ssh_port {} is out of range 1-65535", port));
+                    }
+                }
+                if self.ssh_password.is_some() && self.ssh_key.is_some() {
+                    errors.push("set either ssh_password or ssh_key, not both".to_string());
+                }
+                if self.ssh_key_passphrase.is_some() && self.ssh_key.is_none() {
+                    errors.push("ssh_key_passphrase is set but ssh_key is missing".to_string());
+                }
+                for (index, jump) in self.jump.iter().enumerate() {
+                    for error in jump.validate() {
+                        errors.push(format!("jump[{}]: {}", index, error));
+                    }
+                }
+            }
+            None => {
+                for (key, set) in [
+                    ("ssh_port", self.ssh_port.is_some()),
+                    ("ssh_password", self.ssh_password.is_some()),
+                    ("ssh_key", self.ssh_key.is_some()),
+                    ("ssh_key_passphrase", self.ssh_key_passphrase.is_some()),
+                    ("jump", !self.jump.is_empty()),
+                ] {
+                    if set {
+                        errors.push(format!("{} is set but ssh_user is missing", key));
+                    }
+                }
+                if self.local_port.is_some() {
+                    errors.push("local_port is set but ssh_user is missing".to_string());
+                }
+            }
+        }
+
+        errors
+    }
+
+    // 更新 toml 表中的已知键, 保留原有的行内注释
+    fn write_to(&self, table: &mut Table) -> Result<()> {
+        let doc = toml::to_string(self)?.parse::<Document>()?;
+        for key in SOURCE_KEYS {
+            if !doc.contains_key(key) {
+                table.remove(key);
+            }
+        }
+        for (key, item) in doc.iter() {
+            match (table.get_mut(key), item) {
+                (Some(Item::Value(old)), Item::Value(value)) => {
+                    let decor = old.decor().clone();
+                    *old = value.clone();
+                    *old.decor_mut() = decor;
+                }
+                // 密钥库引用等子表写成行内表
+                (_, Item::Table(sub)) => {
+                    let value = Value::InlineTable(sub.clone().into_inline_table());
+                    table.insert(key, Item::Value(value));
+                }
+                // 跳板机写成 [[sources.jump]], 其中的子表同样写成行内表
+                (_, Item::ArrayOfTables(subs)) => {
+                    let mut subs = subs.clone();
+                    for sub in subs.iter_mut() {
+                        inline_tables(sub);
+                    }
+                    table.insert(key, Item::ArrayOfTables(subs));
+                }
+                _ => {
+                    table.insert(key, item.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl JumpHost {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.ip.trim().is_empty() {
+            errors.push("ip must not be empty".to_string());
+        } else if self.ip.contains(char::is_whitespace) {
+            errors.push("ip must not contain whitespace".to_string());
+        }
+        if let Some(port) = self.port {
+            if !(1..=65535).contains(&port) {
+                errors.push(format!("port {} is out of range 1-65535", port));
+            }
+        }
+        if self.user.trim().is_empty() {
+            errors.push("user must not be empty".to_string());
+        }
+        if self.password.is_some() && self.key.is_some() {
+            errors.push("set either password or key, not both".to_string());
+        }
+        if self.key_passphrase.is_some() && self.key.is_none() {
+            errors.push("key_passphrase is set but key is missing".to_string());
+        }
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(name: &str, ip: &str) -> Source {
+        Source {
+            name: Some(name.to_string()),
+            ip: ip.to_string(),
+            port: 6030,
+            db: "power".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn names(sources: &[Source]) -> Vec<String> {
+        sources.iter().map(Source::label).collect()
+    }
+
+    #[test]
+    fn validate_reports_each_bad_field() {
+        type Case = (&'static str, fn(&mut Source), &'static str);
+        let cases: &[Case] = &[
+            ("missing host", |s| s.ip = String::new(), "ip must not be empty"),
+            ("host with space", |s| s.ip = "10.0.0.1 ".to_string(), "ip must not contain whitespace"),
+            ("zero port", |s| s.port = 0, "port 0 is out of range 1-65535"),
+            ("port too large", |s| s.port = 65536, "port 65536 is out of range 1-65535"),
+            ("missing db", |s| s.db = " ".to_string(), "db must not be empty"),
+            ("token on native", |s| s.token = Some(Secret::Plain("t".to_string())), "token requires protocol ws or wss"),
+            ("password without user", |s| s.password = Some(Secret::Plain("p".to_string())), "password is set but user is missing"),
+            ("ssh port without ssh_user", |s| s.ssh_port = Some(22), "ssh_port is set but ssh_user is missing"),
+            (
+                "bad local_port",
+                |s| {
+                    s.ssh_user = Some("root".to_string());
+                    s.local_port = Some(70000);
+                },
+                "local_port 70000 is out of range 1-65535",
+            ),
+            ("zero timeout", |s| s.query_timeout = Some(0), "query_timeout must be greater than 0"),
+        ];
+        assert!(source("a", "10.0.0.1").validate().is_empty());
+        for (case, edit, expected) in cases {
+            let mut source = source("a", "10.0.0.1");
+            edit(&mut source);
+            assert_eq!(source.validate(), [expected.to_string()], "{}", case);
+        }
+    }
+
+    #[test]
+    fn config_rejects_missing_port_and_duplicate_names() {
+        let path = Path::new("config.toml");
+        let err = parse(path, "[[sources]]\nip = \"10.0.0.1\"\ndb = \"power\"\n").err().unwrap();
+        assert!(format!("{:#}", err).contains("missing field `port`"));
+        let err = parse(path, "sources = []\n").err().unwrap();
+        assert!(format!("{:#}", err).contains("no [[sources]] configured"));
+
+        // 没有 name 时按 ip 判断重复
+        let unnamed = Source {
+            name: None,
+            ..source("", "10.0.0.1")
+        };
+        let config = Config {
+            vault: None,
+            sources: vec![source("a", "10.0.0.1"), source("a", "10.0.0.2"), unnamed.clone(), unnamed],
+        };
+        let err = config.validate().err().unwrap().to_string();
+        assert_eq!(
+            err,
+            "sources[1] (a): duplicate name, set a distinct name\nsources[3] (10.0.0.1): duplicate name, set a distinct name"
+        );
+    }
+
+    #[test]
+    fn config_path_precedence() {
+        let env = [
+            (CONFIG_ENV, "/etc/td.toml"),
+            ("XDG_CONFIG_HOME", "/xdg"),
+            ("HOME", "/home/u"),
+        ];
+        let cases: &[(&[&str], &[&str], &str)] = &[
+            (&["--config", "/a.toml"], &[CONFIG_ENV, "XDG_CONFIG_HOME", "HOME"], "/a.toml"),
+            (&["--other", "--config=/b.toml"], &[CONFIG_ENV], "/b.toml"),
+            (&[], &[CONFIG_ENV, "XDG_CONFIG_HOME", "HOME"], "/etc/td.toml"),
+            (&[], &["XDG_CONFIG_HOME", "HOME"], "/xdg/tdengine-desktop/config.toml"),
+            (&[], &["HOME"], "/home/u/.config/tdengine-desktop/config.toml"),
+        ];
+        for (args, set, expected) in cases {
+            let var = |key: &str| {
+                env.iter()
+                    .find(|(name, _)| *name == key && set.contains(name))
+                    .map(|(_, value)| OsString::from(value))
+            };
+            let path = resolve_path(args.iter().map(|arg| arg.to_string()), var).unwrap();
+            assert_eq!(path, PathBuf::from(expected), "{:?} {:?}", args, set);
+        }
+
+        // 空的环境变量视为未设置
+        let var = |key: &str| (key != "HOME").then(OsString::new);
+        assert!(resolve_path(std::iter::empty(), var).is_err());
+        assert!(resolve_path(["--config".to_string()].into_iter(), |_| None).is_err());
+    }
+
+    #[test]
+    fn diff_finds_added_removed_and_changed() {
+        let old = vec![source("a", "10.0.0.1"), source("b", "10.0.0.2"), source("c", "10.0.0.3")];
+        let mut edited = source("b", "10.0.0.2");
+        edited.query_timeout = Some(30);
+        let new = vec![source("a", "10.0.0.1"), edited, source("d", "10.0.0.4")];
+        let diff = diff(&old, &new);
+        assert_eq!(names(&diff.added), ["d"]);
+        assert_eq!(diff.removed, ["c"]);
+        assert_eq!(names(&diff.changed), ["b"]);
+        assert_eq!(diff.changed[0].query_timeout, Some(30));
+        assert!(diff.affects("b") && diff.affects("c"));
+        assert!(!diff.affects("a") && !diff.affects("d"));
+    }
+
+    #[test]
+    fn diff_treats_rename_as_remove_and_add() {
+        let old = vec![source("a", "10.0.0.1")];
+        let new = vec![source("renamed", "10.0.0.1")];
+        let diff = diff(&old, &new);
+        assert_eq!(names(&diff.added), ["renamed"]);
+        assert_eq!(diff.removed, ["a"]);
+        assert!(diff.changed.is_empty());
+
+        // 没有 name 时按 ip 识别, 改 ip 相当于换了数据源
+        let unnamed = |ip: &str| Source {
+            name: None,
+            ..source("", ip)
+        };
+        let diff = super::diff(&[unnamed("10.0.0.1")], &[unnamed("10.0.0.9")]);
+        assert_eq!(names(&diff.added), ["10.0.0.9"]);
+        assert_eq!(diff.removed, ["10.0.0.1"]);
+    }
+
+    #[test]
+    fn diff_sees_field_edits_but_not_reordering() {
+        let old = vec![source("a", "10.0.0.1"), source("b", "10.0.0.2")];
+        let reordered = vec![source("b", "10.0.0.2"), source("a", "10.0.0.1")];
+        let diff = diff(&old, &reordered);
+        assert!(diff.added.is_empty() && diff.removed.is_empty() && diff.changed.is_empty());
+
+        let mut moved = source("a", "10.0.0.1");
+        moved.port = 6041;
+        moved.protocol = Some(Protocol::Ws);
+        let mut secret = source("b", "10.0.0.2");
+        secret.password = Some(Secret::Vault {
+            vault: "b.password".to_string(),
+        });
+        let diff = super::diff(&old, &[moved, secret]);
+        assert_eq!(names(&diff.changed), ["a", "b"]);
+    }
+
+    #[test]
+    fn save_keeps_comments_unknown_keys_and_order() {
+        let _lock = SAVE_LOCK.lock().unwrap();
+        let path = std::env::temp_dir().join(format!("tdengine-desktop-save-{}.toml", std::process::id()));
+        let text = r#"# 桌面客户端配置
+theme = "dark"
+
+# 生产库
+[[sources]]
+name = "a"
+ip = "10.0.0.1" # 内网地址
+port = 6030
+db = "power"
+color = "red"
+
+[[sources]]
+db = "meters"
+name = "b"
+ip = "10.0.0.2"
+port = 6030
+"#;
+        fs::write(&path, text).unwrap();
+        let config = parse(&path, text).unwrap();
+        let mut edited = config.sources[0].clone();
+        edited.ip = "10.0.0.9".to_string();
+        edited.query_timeout = Some(30);
+        let entries = [
+            SourceEntry {
+                origin: Some(0),
+                source: edited,
+            },
+            SourceEntry {
+                origin: Some(1),
+                source: config.sources[1].clone(),
+            },
+        ];
+        save(&path, &entries).unwrap();
+        // 只有改动的值变化, 新的键追加在表末尾
+        let expected = r#"# 桌面客户端配置
+theme = "dark"
+
+# 生产库
+[[sources]]
+name = "a"
+ip = "10.0.0.9" # 内网地址
+port = 6030
+db = "power"
+color = "red"
+query_timeout = 30
+
+[[sources]]
+db = "meters"
+name = "b"
+ip = "10.0.0.2"
+port = 6030
+"#;
+        assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn watcher_skips_own_saves() {
+        let _lock = SAVE_LOCK.lock().unwrap();
+        let path = std::env::temp_dir().join(format!("tdengine-desktop-watch-{}.toml", std::process::id()));
+        let text = |ip: &str| format!("[[sources]]\nname = \"a\"\nip = \"{}\"\nport = 6030\ndb = \"power\"\n", ip);
+        fs::write(&path, text("10.0.0.1")).unwrap();
+        let mut watcher = Watcher::new(path.clone());
+        assert!(watcher.poll().is_none());
+
+        let entries = [SourceEntry {
+            origin: Some(0),
+            source: source("a", "10.0.0.2"),
+        }];
+        save(&path, &entries).unwrap();
+        assert!(watcher.poll().is_none());
+
+        // 外部编辑仍会重新加载
+        fs::write(&path, text("10.0.0.3")).unwrap();
+        let config = watcher.poll().unwrap().unwrap();
+        assert_eq!(config.sources[0].ip, "10.0.0.3");
+        let _ = fs::remove_file(&path);
+    }
 }
@@ -0,0 +1,204 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use taos::*;
+
+use crate::config::Source;
+use crate::proxy::{self, Proxy};
+use crate::tunnel::{self, Stage, TunnelConfig};
+use crate::tls::Tls;
+use crate::{proxy_config, td, tls_config, tunnel_config, HostData};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// 测试连接中的一步, 失败时 error 为底层错误
+#[derive(Clone, PartialEq)]
+pub struct Step {
+    pub name: String,
+    pub elapsed: Duration,
+    pub detail: String,
+    pub error: Option<String>,
+}
+
+// 依次检查连接数据源的每一步, 某一步失败后不再继续
+pub async fn diagnose(source: Source) -> Vec<Step> {
+    let mut steps = Vec::new();
+    let _ = run(source, &mut steps).await;
+    steps
+}
+
+async fn run(source: Source, steps: &mut Vec<Step>) -> Result<()> {
+    let start = Instant::now();
+    let ((tunnel_config, proxy, tls), mut host_data) = record(steps, "配置和密码".to_string(), start, prepare(&source), |_| {
+        String::new()
+    })?;
+
+    let tunneled = tunnel_config.is_some();
+    match (tunnel_config, proxy.clone()) {
+        (Some(tunnel_config), _) => {
+            let (tunnel, traced) = tokio::task::spawn_blocking(move || {
+                let mut traced = Vec::new();
+                let tunnel = tunnel::open_traced(tunnel_config, &mut |stage, elapsed, error| {
+                    traced.push(Step {
+                        name: stage_name(&stage),
+                        elapsed,
+                        detail: String::new(),
+                        error: error.map(|err| format!("{:#}", err)),
+                    })
+                });
+                (tunnel, traced)
+            })
+            .await?;
+            steps.extend(traced);
+            // 不属于任何一步的错误单独列出
+            if let Err(err) = &tunnel {
+                if steps.last().is_none_or(|step| step.error.is_none()) {
+                    steps.push(Step {
+                        name: "ssh 隧道".to_string(),
+                        elapsed: Duration::ZERO,
+                        detail: String::new(),
+                        error: Some(format!("{:#}", err)),
+                    });
+                }
+            }
+            let tunnel = tunnel?;
+            if let Some(step) = steps.last_mut() {
+                step.detail = format!("本地端口 {}", tunnel.local_port);
+            }
+            host_data.tunnel = Some(tunnel);
+        }
+        // 经代理时由代理解析并连接 TDengine, 之后经本地端口转发
+        (None, Some(proxy)) => {
+            let start = Instant::now();
+            let target = (host_data.ip.clone(), host_data.port as u16);
+            let connected = {
+                let proxy = proxy.clone();
+                let target = target.clone();
+                tokio::task::spawn_blocking(move || proxy.connect(&target.0, target.1)).await?
+            };
+            let name = format!("TCP {}:{} (经代理 {}:{})", target.0, target.1, proxy.host, proxy.port);
+            record(steps, name, start, connected, |_| String::new())?;
+        }
+        (None, None) => {
+            let start = Instant::now();
+            let resolved = resolve(&host_data.ip, host_data.port as u16).await;
+            record(steps, format!("DNS {}", host_data.ip), start, resolved, |address| {
+                address.ip().to_string()
+            })?;
+        }
+    }
+    // 不经 ssh 时, 代理和 wss 的 TLS 都由本地端口转发完成
+    if !tunneled && (proxy.is_some() || tls.is_some()) {
+        let target = (host_data.ip.clone(), host_data.port as u16);
+        if let Some(tls) = tls.clone() {
+            let start = Instant::now();
+            let handshake = {
+                let proxy = proxy.clone();
+                let target = target.clone();
+                tokio::task::spawn_blocking(move || {
+                    let mut remote = proxy::connect(proxy.as_ref(), &target.0, target.1)?;
+                    tls.handshake(&mut remote).map(|_| ())
+                })
+                .await?
+            };
+            record(steps, format!("TLS 握手 {}", target.0), start, handshake, |_| String::new())?;
+        }
+        host_data.relay = Some(proxy::relay(proxy, target, tls)?);
+    }
+
+    // 先不指定数据库连接, 区分认证失败和数据库不存在
+    let address = host_data.address();
+    let mut dsn = host_data.dsn();
+    dsn.subject = None;
+    let start = Instant::now();
+    let handshake = handshake(&address, dsn).await;
+    let builder = record(steps, format!("TDengine 握手 {}", address), start, handshake, |_| String::new())?;
+
+    let start = Instant::now();
+    let auth = async {
+        let taos = tokio::time::timeout(TIMEOUT, builder.build())
+            .await
+            .context("timed out")??;
+        let server = td::Server::detect(&taos).await?;
+        Ok((taos, server))
+    }
+    .await;
+    let user = host_data.user.clone().unwrap_or_else(|| "默认账号".to_string());
+    let (taos, server) = record(steps, format!("TDengine 认证 {}", user), start, auth, |(_, server)| {
+        format!("服务端版本 {}", server.version)
+    })?;
+
+    let start = Instant::now();
+    let stables = async {
+        taos.use_database(&host_data.db).await?;
+        td::STable::get_stables(&taos, &server, &host_data.db).await
+    }
+    .await;
+    record(steps, format!("数据库 {}", host_data.db), start, stables, |stables| {
+        format!("{} 个超级表", stables.len())
+    })?;
+    Ok(())
+}
+
+// 到 TDengine 的路径: ssh 隧道, 或者不经 ssh 时的代理和本地完成的 TLS
+type Route = (Option<TunnelConfig>, Option<Proxy>, Option<Tls>);
+
+fn prepare(source: &Source) -> Result<(Route, HostData)> {
+    let errors = source.validate();
+    if !errors.is_empty() {
+        bail!(errors.join("\n"));
+    }
+    Ok((
+        (tunnel_config(source)?, proxy_config(source)?, tls_config(source)?),
+        HostData::with_secrets(source.clone())?,
+    ))
+}
+
+async fn resolve(host: &str, port: u16) -> Result<std::net::SocketAddr> {
+    tokio::net::lookup_host((host, port))
+        .await
+        .with_context(|| format!("failed to resolve {}", host))?
+        .next()
+        .with_context(|| format!("failed to resolve {}", host))
+}
+
+// 连接 TDengine 端口并确认服务端可用
+async fn handshake(address: &taos_query::Address, dsn: Dsn) -> Result<TaosBuilder> {
+    let host = address.host.clone().unwrap_or_default();
+    let port = address.port.unwrap_or_default();
+    tokio::time::timeout(TIMEOUT, tokio::net::TcpStream::connect((host.as_str(), port)))
+        .await
+        .context("timed out")?
+        .with_context(|| format!("failed to connect to {}", address))?;
+    let builder = TaosBuilder::from_dsn(dsn)?;
+    if !builder.ready().await {
+        bail!("server at {} is not ready", address);
+    }
+    Ok(builder)
+}
+
+fn stage_name(stage: &Stage) -> String {
+    match stage {
+        Stage::Resolve(hop) => format!("DNS {}", hop.host),
+        Stage::Connect(hop) => format!("TCP {}:{}", hop.host, hop.port),
+        Stage::Auth(hop) => format!("SSH 认证 {}@{}", hop.user, hop.host),
+        Stage::Forward(port) => format!("隧道转发 127.0.0.1:{}", port),
+    }
+}
+
+// 记录一步的结果, 成功时由 detail 生成附加信息
+fn record<T>(
+    steps: &mut Vec<Step>,
+    name: String,
+    start: Instant,
+    result: Result<T>,
+    detail: impl FnOnce(&T) -> String,
+) -> Result<T> {
+    steps.push(Step {
+        name,
+        elapsed: start.elapsed(),
+        detail: result.as_ref().map(detail).unwrap_or_default(),
+        error: result.as_ref().err().map(|err| format!("{:#}", err)),
+    });
+    result
+}
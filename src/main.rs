@@ -1,9 +1,12 @@
 use std::cell::{Cell, OnceCell};
 use std::io::prelude::*;
-use std::ops::{Index, IndexMut};
+use std::future::Future;
+use std::pin::Pin;
 use std::string::ToString;
 use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
 
+use anyhow::{Context, Result};
 use dioxus::desktop::{Config, WindowBuilder, wry};
 use dioxus::prelude::*;
 use dioxus_desktop::LogicalSize;
@@ -11,21 +14,42 @@ use dioxus_desktop::tao::dpi::Size::Logical;
 use lazy_static::lazy_static;
 use taos::*;
 
-use config::CONF;
+use config::{CONF, CONF_PATH};
 use message::*;
 
-use crate::config::Source;
+use crate::browse::{Cache, CacheKey, Load, SchemaList, SchemaPane, SubTableItems, SubTableList, SubTableMatches, SubTableSearch, TagGrid, TagGridList};
+use crate::config::{Protocol, Secret, Source};
+use crate::manager::{SourceManager, SourceList};
+use crate::tunnel::{Hop, SshAuth, Tunnel, TunnelConfig};
+use crate::vault::{UnlockList, VaultList, VaultManager, VaultUnlock};
 
+mod browse;
 mod config;
+mod diagnose;
 mod log;
+mod manager;
 mod message;
+mod proxy;
 mod td;
+mod tls;
+mod tunnel;
+mod vault;
 
 static RESIZING: Mutex<Cell<bool>> = Mutex::new(Cell::new(false));
 static X: Mutex<Cell<f64>> = Mutex::new(Cell::new(0.0));
 static TAOS: Mutex<OnceCell<Vec<HostData>>> = Mutex::new(OnceCell::new());
 static TIMES: Mutex<Cell<i64>> = Mutex::new(Cell::new(0));
+// 正在执行的数据查询, 取消按钮通过它终止查询
+static RUNNING: Mutex<Option<td::Control>> = Mutex::new(None);
+// 每次发起数据查询加一, 先发起的查询后返回时丢弃其结果
+static QUERY_GENERATION: AtomicU64 = AtomicU64::new(0);
 static PAGE_SIZE: i64 = 30;
+// 每个主机的连接池大小, 计数、翻页和后台任务可以并行查询
+static POOL_SIZE: usize = 4;
+// 隧道健康检查间隔, 以及断开后重连的最短和最长等待
+static PROBE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15);
+static RETRY_MIN: std::time::Duration = std::time::Duration::from_secs(5);
+static RETRY_MAX: std::time::Duration = std::time::Duration::from_secs(300);
 
 // 这里写死了无法动态计算比例大小.
 static SIZE: (i64, i64) = {
@@ -36,57 +60,44 @@ static SIZE: (i64, i64) = {
 lazy_static! {
     static ref CURRENT_STABLE: Mutex<Cell<String>> = Mutex::new(Cell::new(String::default()));
     static ref PAGE: Mutex<Cell<i32>> = Mutex::new(Cell::new(1));
-    static ref ROBOT_ID: Mutex<Cell<String>> = Mutex::new(Cell::new(String::default()));
+    // 当前表的过滤条件, 切换表时清空
+    static ref FILTER: Mutex<Cell<td::Filter>> = Mutex::new(Cell::new(td::Filter::default()));
+    static ref STARTUP_ERROR: Mutex<Cell<String>> = Mutex::new(Cell::new(String::default()));
 }
-static DATA: Mutex<OnceCell<TableData>> = Mutex::new(OnceCell::new());
 
 fn main() {
-    {
-        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
-        runtime.block_on(async {
-            let mut hosts: Vec<HostData> = Vec::new();
-            for conf in CONF.sources.iter() {
-                let host = connect_host(conf.clone()).await;
-                hosts.push(host);
-            }
-            let current_stable = hosts
-                .get(0)
-                .unwrap()
-                .stables
-                .get(0)
-                .clone()
-                .unwrap()
-                .clone();
-            CURRENT_STABLE.lock().unwrap().set(current_stable);
-            let _ = TAOS.lock().unwrap().set(hosts);
-
-            let start = std::time::Instant::now();
-            let (rows, total_size, headers) = get_rows().await;
-            let l = headers.len();
-            let changed_size = vec![0; headers.len()];
-            let real_moving_size = vec![0; headers.len()];
-            let total_page: i64;
-            if total_size / PAGE_SIZE == 0 && total_size > PAGE_SIZE {
-                total_page = total_size / PAGE_SIZE;
-            } else {
-                total_page = total_size / PAGE_SIZE + 1;
-            };
-            let table_data = TableData {
-                headers,
-                rows,
-                total_size,
-                total_page,
-                changed_size: changed_size.clone(),
-                real_moving_size: real_moving_size.clone(),
-                widths: cal_widths(SIZE.1, l as i64, changed_size, real_moving_size),
-                spend: start.elapsed().as_millis().to_string(),
-            };
-
-            // 不知道改如何将这个数据传入App,只能借助全局变量
-            DATA.lock().unwrap().set(table_data).unwrap();
-        });
+    match config::load() {
+        Ok((path, conf)) => {
+            *CONF_PATH.lock().unwrap() = path;
+            *CONF.lock().unwrap() = conf;
+        }
+        Err(err) => {
+            // 配置有误时不连接任何主机, 直接展示错误页
+            STARTUP_ERROR.lock().unwrap().set(format!("{:#}", err));
+            launch(StartupError);
+            return;
+        }
     }
 
+    // 窗口先打开, 主机在选中时才连接
+    init_hosts();
+    launch(App);
+}
+
+// 为每个数据源创建未连接的主机
+fn init_hosts() {
+    let hosts = CONF
+        .lock()
+        .unwrap()
+        .sources
+        .iter()
+        .cloned()
+        .map(HostData::new)
+        .collect::<Vec<HostData>>();
+    let _ = TAOS.lock().unwrap().set(hosts);
+}
+
+fn launch(app: fn() -> Element) {
     LaunchBuilder::desktop().with_cfg(
         Config::new()
             .with_custom_head(r#"<link rel="stylesheet" href="public/tailwind.css">"#.to_string())
@@ -94,33 +105,65 @@ fn main() {
             .with_window(WindowBuilder::new().with_resizable(true).with_inner_size(
                 Logical(LogicalSize::new(1800.0, 1200.0))
             )),
-    ).launch(App);
+    ).launch(app);
 }
 
-async fn get_rows() -> (Vec<Vec<String>>, i64, Vec<String>) {
+// 发起新的数据查询, 返回它的序号
+fn next_query() -> u64 {
+    QUERY_GENERATION.fetch_add(1, Ordering::SeqCst) + 1
+}
+
+// 查询结果, 已有更新的查询时为 None
+async fn get_rows(token: u64) -> Option<(Vec<Vec<String>>, i64, Vec<String>, QueryState)> {
     let page = PAGE.lock().unwrap().get();
     let stable = CURRENT_STABLE.lock().unwrap().get_mut().clone();
-    let robot_id = ROBOT_ID.lock().unwrap().get_mut().clone();
-    let robot_id = if robot_id.is_empty() {
-        None
-    } else {
-        Some(robot_id)
+    let filter = FILTER.lock().unwrap().get_mut().clone();
+
+    // 取出连接池后释放锁, 查询期间不阻塞界面
+    let host = TAOS
+        .lock()
+        .unwrap()
+        .get()
+        .and_then(|hosts| hosts.first())
+        .and_then(|host| {
+            Some((
+                host.pool.clone()?,
+                host.db.clone(),
+                host.server.clone(),
+                host.time_display.clone(),
+                host.query_timeout,
+            ))
+        });
+    // 主机未连接或数据库中没有超表时显示空表
+    let Some((pool, db, server, display, timeout)) = host.filter(|_| !stable.is_empty()) else {
+        return (QUERY_GENERATION.load(Ordering::SeqCst) == token).then(|| (Vec::new(), 0, Vec::new(), QueryState::Done));
+    };
+    let control = td::Control {
+        timeout,
+        ..Default::default()
     };
+    *RUNNING.lock().unwrap() = Some(control.clone());
 
-    let (mut rt, count) = {
-        let mut taos = TAOS.lock().unwrap();
-        let taos = taos.get_mut().unwrap();
-        let taos = match &taos.index(0).taos {
-            Some(taos) => taos,
-            _ => {
-                panic!("")
+    let result = td::STable::new(db, stable).get_rows(&pool, &server, page, &filter, &display, &control).await;
+    // 只清除自己的取消信号, 之后发起的查询可能已替换它
+    let mut running = RUNNING.lock().unwrap();
+    if running.as_ref().is_some_and(|c| std::sync::Arc::ptr_eq(&c.cancel, &control.cancel)) {
+        *running = None;
+    }
+    drop(running);
+    if QUERY_GENERATION.load(Ordering::SeqCst) != token {
+        return None;
+    }
+
+    let (mut rt, count, state) = match result {
+        Ok((rows, count)) => (rows, count, QueryState::Done),
+        Err(err) => match err.downcast_ref::<td::Interrupted>() {
+            Some(interrupted) => (vec![Vec::new()], Some(0), QueryState::Interrupted(*interrupted)),
+            None => {
+                log::info(format!("query failed: {:#}", err));
+                return Some((Vec::new(), 0, Vec::new(), QueryState::Failed(format!("{:#}", err))));
             }
-        };
-        let rows = td::STable::new(stable)
-            .get_rows(taos, page, robot_id)
-            .await
-            .unwrap();
-        rows
+        },
     };
 
     let mut from_headers = vec![];
@@ -128,9 +171,16 @@ async fn get_rows() -> (Vec<Vec<String>>, i64, Vec<String>) {
         from_headers.push(item.to_string())
     }
     if rt.len() == 1 {
-        (Vec::new(), count.unwrap(), from_headers)
+        Some((Vec::new(), count.unwrap(), from_headers, state))
     } else {
-        (rt.split_off(1), count.unwrap(), from_headers)
+        Some((rt.split_off(1), count.unwrap(), from_headers, state))
+    }
+}
+
+// 取消正在执行的查询
+fn cancel_query() {
+    if let Some(control) = RUNNING.lock().unwrap().as_ref() {
+        control.cancel.notify_one();
     }
 }
 
@@ -141,34 +191,134 @@ fn App() -> Element {
 
     let nav_width = SIZE.0;
     let table_width = SIZE.1;
-    let table_data_state: Signal<TableData> = use_signal(|| {
-        DATA.lock().unwrap().take().unwrap()
+    let table_data_state: Signal<TableData> = use_signal(TableData::default);
+    // 引用了密钥库时先解锁, 再连接主机
+    let mut locked = use_signal(|| CONF.lock().unwrap().uses_vault() && !vault::is_unlocked());
+
+    let mut show_manager = use_signal(|| false);
+    let mut show_vault = use_signal(|| false);
+    let mut config_error = use_signal(String::new);
+    // 刷新次数, 标签和子表等缓存据此失效
+    let mut refresh = use_signal(|| 0u64);
+
+    // 打开窗口后在后台连接第一个主机
+    use_hook(move || {
+        if !locked() {
+            spawn(message_handler(Message::SelectHost(current_host(), table_width, table_data_state)));
+        }
+    });
+
+    // 定时检查 ssh 隧道, 断开后按退避时间重连
+    use_future(move || async move {
+        loop {
+            tokio::time::sleep(PROBE_INTERVAL).await;
+            message_handler(Message::CheckTunnels(table_width, table_data_state)).await;
+        }
+    });
+    // 窗口关闭时断开所有隧道
+    use_drop(shutdown_hosts);
+
+    // 监听配置文件, 变化后只重连受影响的主机
+    use_future(move || async move {
+        let mut watcher = config::Watcher::new(CONF_PATH.lock().unwrap().clone());
+        loop {
+            tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+            match watcher.poll() {
+                Some(Ok(config)) => {
+                    config_error.set(String::new());
+                    if config != *CONF.lock().unwrap() {
+                        message_handler(Message::ReloadHosts(config, table_width, table_data_state)).await;
+                    }
+                }
+                Some(Err(err)) => config_error.set(format!("{:#}", err)),
+                None => {}
+            }
+        }
     });
 
     *TIMES.lock().unwrap().get_mut() += 1;
 
+    let state = current_state();
+    let no_stables = stables.is_empty();
+    // 选中的是超表时才有标签面板
+    let current_stable = CURRENT_STABLE.lock().unwrap().get_mut().clone();
+    let is_stable = stables.contains(&current_stable);
+    let (databases, current_db, stables_error) = current_databases();
+    let propsc = TagList {
+        width: table_width,
+        current_host: current_host(),
+        current_db: current_db.clone(),
+        stable: current_stable.clone(),
+        filters: FILTER.lock().unwrap().get_mut().tags.clone(),
+        refresh,
+        on_filter: EventHandler::new({
+            move |filters: Vec<td::TagFilter>| {
+                spawn(message_handler(Message::TagFilter(filters, table_data_state)));
+            }
+        }),
+        on_open: EventHandler::new({
+            move |table: String| {
+                spawn(message_handler(Message::ChangeStable(table, table_width, table_data_state)));
+            }
+        }),
+    };
     let propsa = StablesList {
         width: nav_width,
         stables,
+        current_host: current_host(),
+        hosts: host_summaries(),
+        databases,
+        current_db: current_db.clone(),
+        stables_error: stables_error.clone(),
         on_stable_change: EventHandler::new({
             move |msg: String| {
                 spawn(message_handler(Message::ChangeStable(msg, table_width, table_data_state.clone())));
             }
         }),
         on_host_change: EventHandler::new({
-            move |ip: String| {
-                let stable = CURRENT_STABLE.lock().unwrap().get_mut().clone();
-                turn_taos(ip);
-                spawn(message_handler(Message::ChangeStable(stable, table_width, table_data_state.clone())));
+            move |name: String| {
+                spawn(message_handler(Message::SelectHost(name, table_width, table_data_state)));
             }
         }),
+        on_db_change: EventHandler::new({
+            move |db: String| {
+                spawn(message_handler(Message::SelectDatabase(db, table_width, table_data_state)));
+            }
+        }),
+        on_manage: EventHandler::new(move |_| show_manager.set(true)),
+        on_vault: EventHandler::new(move |_| show_vault.set(true)),
+        refresh,
     };
     let propsb = TableList {
         width: table_width,
         table_data: table_data_state.clone(),
         on_search: EventHandler::new({
-            move |msg: String| {
-                spawn(message_handler(Message::StableFilter(msg, table_data_state.clone())));
+            move |(join, predicates): (td::Join, Vec<td::Predicate>)| {
+                let filter = td::Filter {
+                    join,
+                    predicates,
+                    ..FILTER.lock().unwrap().get_mut().clone()
+                };
+                spawn(message_handler(Message::StableFilter(filter, table_data_state.clone())));
+            }
+        }),
+        on_sort: EventHandler::new({
+            move |column: String| {
+                let filter = FILTER.lock().unwrap().get_mut().clone();
+                let filter = td::Filter {
+                    order: filter.order.toggle(&column),
+                    ..filter
+                };
+                spawn(message_handler(Message::StableFilter(filter, table_data_state)));
+            }
+        }),
+        on_range: EventHandler::new({
+            move |range: td::TimeRange| {
+                let filter = td::Filter {
+                    range,
+                    ..FILTER.lock().unwrap().get_mut().clone()
+                };
+                spawn(message_handler(Message::StableFilter(filter, table_data_state)));
             }
         }),
         on_resize: EventHandler::new({
@@ -182,20 +332,113 @@ fn App() -> Element {
             }
         }),
         on_refresh: EventHandler::new({
-            move |msg: String| {
-                spawn(message_handler(Message::StableFilter(msg, table_data_state.clone())));
+            move |_| {
+                *refresh.write() += 1;
+                let filter = FILTER.lock().unwrap().get_mut().clone();
+                spawn(message_handler(Message::StableFilter(filter, table_data_state.clone())));
             }
         }),
+        on_cancel: EventHandler::new(move |_| {
+            spawn(message_handler(Message::CancelQuery));
+        }),
+        order_by_any: current_features().order_by_any,
+        current_host: current_host(),
+        current_db,
+        current_table: current_stable.clone(),
+        is_stable,
+        filter: FILTER.lock().unwrap().get_mut().clone(),
     };
+    if locked() {
+        return rsx! {
+            VaultUnlock {
+                props: UnlockList {
+                    on_unlocked: EventHandler::new(move |_| {
+                        locked.set(false);
+                        spawn(message_handler(Message::SelectHost(current_host(), table_width, table_data_state)));
+                    }),
+                },
+            }
+        };
+    }
+
+    let plaintext_secrets = CONF.lock().unwrap().plaintext_secrets().len();
     rsx! {
+        if plaintext_secrets > 0 {
+            div {
+                class: "text-amber-600 border border-amber-300 rounded-md p-2 m-1 cursor-pointer",
+                onclick: move |_| show_vault.set(true),
+                "配置中有 {plaintext_secrets} 个明文密码, 点击迁移到密钥库"
+            }
+        }
+        if !config_error.read().is_empty() {
+            pre {
+                class: "text-red-500 whitespace-pre-wrap border border-red-300 rounded-md p-2 m-1",
+                "配置文件有误, 继续使用之前的配置:\n{config_error}"
+            }
+        }
         div {
             class: "flex p-1",
             font_family: "hack",
             Stables {
                 props: propsa,
             }
-            Table {
-                props: propsb,
+            if let HostState::Failed(reason) = state.clone() {
+                div {
+                    class: "p-4",
+                    style: "width:{table_width}px",
+                    p { class: "text-xl font-bold text-red-500 mb-2", "连接 {current_host()} 失败" }
+                    pre { class: "text-gray-600 whitespace-pre-wrap border border-slate-300 rounded-md p-2 mb-2", "{reason}" }
+                    button {
+                        class: "bg-sky-500 hover:bg-sky-700 text-white font-bold py-2 px-4 rounded",
+                        onclick: move |_| {
+                            spawn(message_handler(Message::SelectHost(current_host(), table_width, table_data_state)));
+                        },
+                        "重试"
+                    }
+                }
+            } else if state == HostState::Connected && no_stables && stables_error.is_empty() {
+                div {
+                    class: "p-4 text-gray-400",
+                    style: "width:{table_width}px",
+                    "数据库中没有超级表"
+                }
+            } else if state == HostState::Connected {
+                div {
+                    if is_stable {
+                        Tags {
+                            props: propsc,
+                        }
+                    }
+                    Table {
+                        props: propsb,
+                    }
+                }
+            } else {
+                div {
+                    class: "p-4 text-gray-400",
+                    style: "width:{table_width}px",
+                    "正在连接 {current_host()} ..."
+                }
+            }
+        }
+        if show_vault() {
+            VaultManager {
+                props: VaultList {
+                    on_close: EventHandler::new(move |_| show_vault.set(false)),
+                    on_config_changed: EventHandler::new(move |config| {
+                        spawn(message_handler(Message::ReloadHosts(config, table_width, table_data_state.clone())));
+                    }),
+                },
+            }
+        }
+        if show_manager() {
+            SourceManager {
+                props: SourceList {
+                    on_close: EventHandler::new(move |_| show_manager.set(false)),
+                    on_saved: EventHandler::new(move |config| {
+                        spawn(message_handler(Message::ReloadHosts(config, table_width, table_data_state.clone())));
+                    }),
+                },
             }
         }
         div {
@@ -232,40 +475,60 @@ fn App() -> Element {
     }
 }
 
+#[allow(non_snake_case)]
+fn StartupError() -> Element {
+    let message = STARTUP_ERROR.lock().unwrap().get_mut().clone();
+    let config_env = config::CONFIG_ENV;
+    rsx! {
+        div {
+            class: "p-4",
+            font_family: "hack",
+            p { class: "text-xl font-bold text-red-500 mb-2", "配置加载失败" }
+            pre { class: "text-gray-600 whitespace-pre-wrap border border-slate-300 rounded-md p-2", "{message}" }
+            p {
+                class: "text-gray-400 mt-2",
+                "配置文件查找顺序: --config <path>, ${config_env}, $XDG_CONFIG_HOME/tdengine-desktop/config.toml"
+            }
+        }
+    }
+}
+
 #[derive(Props, Clone, PartialEq)]
 struct TableList {
     table_data: Signal<TableData>,
     width: i64,
-    on_search: EventHandler<String>,
+    on_search: EventHandler<(td::Join, Vec<td::Predicate>)>,
+    on_range: EventHandler<td::TimeRange>,
+    // 点击表头时按该列排序, 服务端不支持时只能按 ts 排序
+    on_sort: EventHandler<String>,
+    order_by_any: bool,
     on_resize: EventHandler<(i64, i64)>,
     on_resize_over: EventHandler,
-    on_refresh: EventHandler<String>,
+    on_refresh: EventHandler,
+    on_cancel: EventHandler,
+    // 表结构面板显示的表
+    current_host: String,
+    current_db: String,
+    current_table: String,
+    is_stable: bool,
+    // 当前生效的过滤条件
+    filter: td::Filter,
 }
 
 #[allow(non_snake_case)]
 #[component]
 fn Table(props: TableList) -> Element {
-    let mut robot_id_state = use_signal(|| "".to_string());
+    let mut show_filter = use_signal(|| false);
+    let mut show_schema = use_signal(|| false);
+    let applied = props.filter.predicates.len();
     rsx! {
         div { style: "width:{props.width}px",
             div { class: "flex",
-                div { class: "basis-1/4",
-                    input {
-                        placeholder: "robotId",
-                        class: "placeholder:italic placeholder:text-slate-400 block bg-white w-full border border-slate-300 rounded-md py-2 pl-9 pr-3 shadow-sm focus:outline-none focus:border-sky-500 focus:ring-sky-500 focus:ring-1 sm:text-sm",
-                        oninput: move |evt| robot_id_state.set(evt.value())
-                    }
-                }
                 div {
                     button {
                         class: "bg-sky-500 hover:bg-sky-700 text-white font-bold py-2 px-4 rounded",
-                        onclick: {
-                            let on_search = props.on_search.clone();
-                            move |_| {
-                                on_search.call(robot_id_state.to_string())
-                            }
-                        },
-                        "搜索"
+                        onclick: move |_| show_filter.set(!show_filter()),
+                        if applied > 0 { "筛选 ({applied})" } else { "筛选" }
                     }
                 }
                 div {
@@ -275,81 +538,507 @@ fn Table(props: TableList) -> Element {
                         onclick: {
                             let on_refresh = props.on_refresh.clone();
                             move |_| {
-                             on_refresh.call(robot_id_state.to_string());
+                             on_refresh.call(());
                             }
                         },
                         "刷新"
                     }
                 }
+                div {
+                    class: "ml-2",
+                    button {
+                        class: "bg-sky-300 hover:bg-sky-500 text-white font-bold py-2 px-4 rounded",
+                        onclick: move |_| show_schema.set(!show_schema()),
+                        if show_schema() { "隐藏表结构" } else { "表结构" }
+                    }
+                }
                 div { class: "text-rose-400 flex justify-center items-center ml-auto",
-                    p { " {props.table_data.read().spend}ms" }
-                }
-            }
-            table {
-                class: "border border-slate-400 text-gray-600 ",
-                table_layout: "fixed",
-                border: "1",
-                width: "100%",
-                thead {
-                    tr {
-                        for (index , header) in props.table_data.read().headers.iter().enumerate() {
-                            td {
-                                class: "border border-slate-300 bg-sky-500 text-white text-left hover:cursor-pointer overflow-clip",
-                                style: "txt-overflow: ellipsis;white-space: nowrap;",
-                                onmousedown: |_| {
-                                    let window = dioxus_desktop::use_window();
-                                    RESIZING.lock().unwrap().set(true);
-                                    X.lock().unwrap().set(window.cursor_position().unwrap().x);
+                    match props.table_data.read().query.clone() {
+                        QueryState::Running => rsx! {
+                            p { class: "text-gray-500", "查询中..." }
+                            button {
+                                class: "ml-2 bg-rose-500 hover:bg-rose-700 text-white font-bold py-1 px-3 rounded",
+                                onclick: {
+                                    let on_cancel = props.on_cancel.clone();
+                                    move |_| on_cancel.call(())
                                 },
-                                onmousemove: {
-                                    let on_resize = props.on_resize.clone();
-                                    move |_e| {
-                                        if RESIZING.lock().unwrap().get() {
+                                "取消"
+                            }
+                        },
+                        QueryState::Interrupted(td::Interrupted::Cancelled(true)) => rsx! {
+                            p { class: "font-bold", "查询已取消, 已终止服务端查询" }
+                        },
+                        QueryState::Interrupted(td::Interrupted::Cancelled(false)) => rsx! {
+                            p { class: "font-bold", "查询已在本地取消, 服务端查询可能仍在执行" }
+                        },
+                        QueryState::Interrupted(td::Interrupted::TimedOut(timeout, true)) => rsx! {
+                            p { class: "font-bold", "查询超过 {timeout.as_secs()}s, 已终止服务端查询" }
+                        },
+                        QueryState::Interrupted(td::Interrupted::TimedOut(timeout, false)) => rsx! {
+                            p { class: "font-bold", "查询超过 {timeout.as_secs()}s, 已在本地停止等待, 服务端查询可能仍在执行" }
+                        },
+                        QueryState::Failed(_) => rsx! {
+                            p { class: "font-bold", "查询失败" }
+                        },
+                        QueryState::Done => rsx! {
+                            p { " {props.table_data.read().spend}ms" }
+                        },
+                    }
+                }
+            }
+            TimeRangePicker {
+                key: "{props.filter.range.start}/{props.filter.range.end}",
+                props: RangeList {
+                    range: props.filter.range.clone(),
+                    on_range: props.on_range.clone(),
+                },
+            }
+            if show_filter() && !props.current_table.is_empty() {
+                // 切换表时重新挂载, 重置编辑中的条件
+                FilterBuilder {
+                    key: "{props.current_host}/{props.current_db}/{props.current_table}",
+                    props: FilterList {
+                        current_host: props.current_host.clone(),
+                        current_db: props.current_db.clone(),
+                        table: props.current_table.clone(),
+                        filter: props.filter.clone(),
+                        on_search: props.on_search.clone(),
+                    },
+                }
+            }
+            // 出错时表格仍是上一次成功查询的数据
+            if let QueryState::Failed(err) = &props.table_data.read().query {
+                p { class: "text-red-500 p-2", "查询失败, 显示的是上一次的数据: {err}" }
+            }
+            div {
+                class: "flex",
+                div {
+                    class: "flex-1 overflow-auto",
+                    table {
+                        class: "border border-slate-400 text-gray-600 ",
+                        table_layout: "fixed",
+                        border: "1",
+                        width: "100%",
+                        thead {
+                            tr {
+                                for (index , header) in props.table_data.read().headers.iter().enumerate() {
+                                    td {
+                                        class: "border border-slate-300 bg-sky-500 text-white text-left hover:cursor-pointer overflow-clip",
+                                        style: "txt-overflow: ellipsis;white-space: nowrap;",
+                                        onmousedown: |_| {
                                             let window = dioxus_desktop::use_window();
-                                            let move_size = window.cursor_position().unwrap().x
-                                                - X.lock().unwrap().get();
-                                            on_resize.call((index as i64, move_size as i64));
+                                            RESIZING.lock().unwrap().set(true);
+                                            X.lock().unwrap().set(window.cursor_position().unwrap().x);
+                                        },
+                                        onmousemove: {
+                                            let on_resize = props.on_resize.clone();
+                                            move |_e| {
+                                                if RESIZING.lock().unwrap().get() {
+                                                    let window = dioxus_desktop::use_window();
+                                                    let move_size = window.cursor_position().unwrap().x
+                                                        - X.lock().unwrap().get();
+                                                    on_resize.call((index as i64, move_size as i64));
+                                                }
+                                            }
+                                        },
+                                        onmouseup: {
+                                            let on_resize_over = props.on_resize_over.clone();
+                                            move |_| {
+                                                RESIZING.lock().unwrap().set(false);
+                                                on_resize_over.call(());
+                                            }
+                                        },
+                                        onmouseleave: {
+                                            let on_resize_over = props.on_resize_over.clone();
+                                            move |_| {
+                                                if RESIZING.lock().unwrap().get() {
+                                                    RESIZING.lock().unwrap().set(false);
+                                                    on_resize_over.call(());
+                                                }
+                                            }
+                                        },
+                                        if props.order_by_any || header == "ts" {
+                                            // 点击列名排序, 在列名上按下时不开始调整列宽
+                                            span {
+                                                class: "hover:underline",
+                                                onmousedown: |evt| evt.stop_propagation(),
+                                                onclick: {
+                                                    let on_sort = props.on_sort.clone();
+                                                    let header = header.clone();
+                                                    move |_| on_sort.call(header.clone())
+                                                },
+                                                if props.filter.order.column == *header {
+                                                    if props.filter.order.descending { " {header} ▼" } else { " {header} ▲" }
+                                                } else {
+                                                    " {header}"
+                                                }
+                                            }
+                                        } else {
+                                            " {header}"
                                         }
                                     }
-                                },
-                                onmouseup: {
-                                    let on_resize_over = props.on_resize_over.clone();
-                                    move |_| {
-                                        RESIZING.lock().unwrap().set(false);
-                                        on_resize_over.call(());
-                                    }
-                                },
-                                onmouseleave: {
-                                    let on_resize_over = props.on_resize_over.clone();
-                                    move |_| {
-                                        if RESIZING.lock().unwrap().get() {
-                                            RESIZING.lock().unwrap().set(false);
-                                            on_resize_over.call(());
+                                }
+                            }
+                        }
+                        colgroup {
+                            for (index , _row) in props.table_data.read().headers.iter().enumerate() {
+                                col { style: "width:{props.table_data.read().widths.get(index).unwrap()}px" }
+                            }
+                        }
+                        tbody {
+                            for row in props.table_data.read().rows.iter() {
+                                tr {
+                                    for (_index , cell) in row.iter().enumerate() {
+                                        td {
+                                            class: "border border-slate-300 overflow-clip text-left",
+                                            style: "txt-overflow: ellipsis;white-space: nowrap;",
+                                            "{cell}"
                                         }
                                     }
-                                },
-                                " {header}"
+                                }
                             }
                         }
                     }
                 }
-                colgroup {
-                    for (index , _row) in props.table_data.read().headers.iter().enumerate() {
-                        col { style: "width:{props.table_data.read().widths.get(index).unwrap()}px" }
+                if show_schema() && !props.current_table.is_empty() {
+                    // 切换表时重新挂载并加载
+                    SchemaPane {
+                        key: "{props.current_host}/{props.current_db}/{props.current_table}",
+                        props: SchemaList {
+                            current_host: props.current_host.clone(),
+                            current_db: props.current_db.clone(),
+                            table: props.current_table.clone(),
+                            stable: props.is_stable,
+                        },
                     }
                 }
-                tbody {
-                    for row in props.table_data.read().rows.iter() {
-                        tr {
-                            for (_index , cell) in row.iter().enumerate() {
-                                td {
-                                    class: "border border-slate-300 overflow-clip text-left",
-                                    style: "txt-overflow: ellipsis;white-space: nowrap;",
-                                    "{cell}"
-                                }
+            }
+        }
+    }
+}
+
+#[derive(Props, PartialEq, Clone)]
+struct RangeList {
+    // 当前生效的时间范围
+    range: td::TimeRange,
+    on_range: EventHandler<td::TimeRange>,
+}
+
+// 常用的最近时间段
+static RANGE_PRESETS: [(&str, &str); 4] = [("15m", "最近 15 分钟"), ("1h", "最近 1 小时"), ("24h", "最近 24 小时"), ("7d", "最近 7 天")];
+
+#[allow(non_snake_case)]
+#[component]
+fn TimeRangePicker(props: RangeList) -> Element {
+    // 编辑中的起止时间, 组件按生效的范围挂载, 范围变化后从新范围开始编辑
+    let mut draft = use_signal(|| props.range.clone());
+    let mut error = use_signal(String::new);
+    let apply = {
+        let on_range = props.on_range.clone();
+        move |range: td::TimeRange| match range.check() {
+            Ok(()) => {
+                error.set(String::new());
+                on_range.call(range);
+            }
+            Err(err) => error.set(format!("{:#}", err)),
+        }
+    };
+    let input = "border border-slate-300 rounded-md px-2 py-1";
+    let preset = |selected: bool| {
+        if selected {
+            "bg-sky-500 text-white px-2 py-1 rounded mr-1"
+        } else {
+            "bg-sky-100 hover:bg-sky-300 text-sky-700 px-2 py-1 rounded mr-1"
+        }
+    };
+    rsx! {
+        div {
+            class: "flex items-center flex-wrap my-1 text-gray-600",
+            button {
+                class: preset(props.range.is_empty()),
+                onclick: {
+                    let mut apply = apply.clone();
+                    move |_| apply(td::TimeRange::default())
+                },
+                "全部时间"
+            }
+            for (duration, label) in RANGE_PRESETS {
+                button {
+                    class: preset(props.range == td::TimeRange::last(duration)),
+                    onclick: {
+                        let mut apply = apply.clone();
+                        move |_| apply(td::TimeRange::last(duration))
+                    },
+                    "{label}"
+                }
+            }
+            for (index, placeholder) in [(0, "开始, 如 now-2h"), (1, "结束, 如 now")] {
+                input {
+                    class: "{input} ml-2 w-44",
+                    placeholder: placeholder,
+                    value: if index == 0 { "{draft.read().start}" } else { "{draft.read().end}" },
+                    oninput: move |e: Event<FormData>| {
+                        draft.with_mut(|draft| {
+                            if index == 0 {
+                                draft.start = e.value();
+                            } else {
+                                draft.end = e.value();
+                            }
+                        });
+                    },
+                }
+                // 选择绝对时间后填入左边的输入框
+                input {
+                    class: "{input} w-8",
+                    r#type: "datetime-local",
+                    step: "1",
+                    onchange: move |e: Event<FormData>| {
+                        let value = e.value().replace('T', " ");
+                        draft.with_mut(|draft| {
+                            if index == 0 {
+                                draft.start = value;
+                            } else {
+                                draft.end = value;
+                            }
+                        });
+                    },
+                }
+            }
+            button {
+                class: "ml-2 bg-sky-500 hover:bg-sky-700 text-white font-bold px-4 py-1 rounded",
+                onclick: {
+                    let mut apply = apply.clone();
+                    move |_| {
+                        let range = draft.read().clone();
+                        apply(range);
+                    }
+                },
+                "应用"
+            }
+            if !error.read().is_empty() {
+                p { class: "text-red-500 ml-2", "{error}" }
+            }
+        }
+    }
+}
+
+// 编辑中的过滤条件, between 使用两个值, in 的多个值用逗号分隔
+#[derive(Clone, PartialEq, Default)]
+struct Draft {
+    column: String,
+    operator: Option<td::Operator>,
+    values: [String; 2],
+}
+
+impl Draft {
+    fn from_predicate(predicate: &td::Predicate) -> Draft {
+        let values = match predicate.operator {
+            td::Operator::In => [predicate.values.join(", "), String::new()],
+            _ => [0, 1].map(|index| predicate.values.get(index).cloned().unwrap_or_default()),
+        };
+        Draft {
+            column: predicate.column.name.clone(),
+            operator: Some(predicate.operator),
+            values,
+        }
+    }
+
+    fn to_predicate(&self, columns: &[td::Column]) -> Result<td::Predicate> {
+        let column = columns
+            .iter()
+            .find(|column| column.name == self.column)
+            .context("choose a column for every condition")?;
+        let operator = self
+            .operator
+            .filter(|operator| column.operators().contains(operator))
+            .with_context(|| format!("choose an operator for {}", column.name))?;
+        let values = match operator {
+            td::Operator::In => self.values[0]
+                .split(',')
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+                .collect::<Vec<String>>(),
+            _ => self.values[..operator.arity()].to_vec(),
+        };
+        if values.is_empty() && operator.arity() > 0 || values.iter().any(|value| value.is_empty()) {
+            anyhow::bail!("{} {} needs a value", column.name, operator.label());
+        }
+        let predicate = td::Predicate {
+            column: column.clone(),
+            operator,
+            values,
+        };
+        // 提前检查值是否符合列类型, 时间的时区转换在查询时按数据源进行
+        predicate.condition(&td::TimeDisplay::default())?;
+        Ok(predicate)
+    }
+}
+
+#[derive(Props, PartialEq, Clone)]
+struct FilterList {
+    current_host: String,
+    current_db: String,
+    table: String,
+    filter: td::Filter,
+    on_search: EventHandler<(td::Join, Vec<td::Predicate>)>,
+}
+
+#[allow(non_snake_case)]
+#[component]
+fn FilterBuilder(props: FilterList) -> Element {
+    // 组件按表挂载, 编辑中的条件从当前生效的条件开始, 列在挂载时查询一次
+    let mut drafts = use_signal(|| {
+        (
+            props.filter.join,
+            props.filter.predicates.iter().map(Draft::from_predicate).collect::<Vec<Draft>>(),
+        )
+    });
+    let mut error = use_signal(String::new);
+    let columns = use_resource({
+        let key = (props.current_host.clone(), props.current_db.clone(), props.table.clone());
+        move || {
+            let (name, db, table) = key.clone();
+            async move { Load::from(get_columns(name, db, table).await) }
+        }
+    });
+    let state = columns.value().read().clone();
+    let loaded = match &state {
+        Some(Load::Loaded(loaded)) => loaded.clone(),
+        _ => Vec::new(),
+    };
+    let operators = |columns: &[td::Column], name: &str| {
+        columns
+            .iter()
+            .find(|column| column.name == name)
+            .map(|column| column.operators())
+            .unwrap_or_default()
+    };
+    let input = "border border-slate-300 rounded-md px-2 py-1 mr-1";
+    rsx! {
+        div {
+            class: "border rounded-md p-2 my-1 text-gray-600",
+            if let Some(Load::Failed(err)) = state.clone() {
+                p { class: "text-red-500", "加载列失败: {err}" }
+            }
+            for (index, draft) in drafts.read().1.iter().cloned().enumerate() {
+                div {
+                    class: "flex items-center mb-1",
+                    if index > 0 {
+                        span { class: "w-12 text-gray-400", "{drafts.read().0.label()}" }
+                    } else {
+                        span { class: "w-12 text-gray-400", "where" }
+                    }
+                    select {
+                        class: "{input}",
+                        onchange: {
+                            let loaded = loaded.clone();
+                            move |e: Event<FormData>| {
+                                let name = e.value();
+                                let operator = operators(&loaded, &name).first().copied();
+                                drafts.with_mut(|drafts| {
+                                    drafts.1[index] = Draft {
+                                        column: name,
+                                        operator,
+                                        ..Default::default()
+                                    };
+                                });
+                            }
+                        },
+                        option { value: "", selected: draft.column.is_empty(), "选择列" }
+                        for column in loaded.iter() {
+                            option {
+                                value: "{column.name}",
+                                selected: column.name == draft.column,
+                                if column.tag { "{column.name} ({column.kind}, 标签)" } else { "{column.name} ({column.kind})" }
                             }
                         }
                     }
+                    select {
+                        class: "{input}",
+                        onchange: {
+                            let available = operators(&loaded, &draft.column);
+                            move |e: Event<FormData>| {
+                                let operator = available.iter().find(|operator| operator.label() == e.value()).copied();
+                                drafts.with_mut(|drafts| drafts.1[index].operator = operator);
+                            }
+                        },
+                        for operator in operators(&loaded, &draft.column) {
+                            option {
+                                value: "{operator.label()}",
+                                selected: Some(operator) == draft.operator,
+                                "{operator.label()}"
+                            }
+                        }
+                    }
+                    for position in 0..draft.operator.map(|operator| operator.arity()).unwrap_or(1) {
+                        input {
+                            class: "{input}",
+                            placeholder: if draft.operator == Some(td::Operator::In) { "多个值用逗号分隔" } else { "值" },
+                            value: "{draft.values[position]}",
+                            oninput: move |e: Event<FormData>| {
+                                drafts.with_mut(|drafts| drafts.1[index].values[position] = e.value());
+                            },
+                        }
+                    }
+                    button {
+                        class: "text-gray-400 hover:text-red-500 px-2",
+                        onclick: move |_| {
+                            drafts.with_mut(|drafts| {
+                                drafts.1.remove(index);
+                            });
+                        },
+                        "×"
+                    }
+                }
+            }
+            div {
+                class: "flex items-center",
+                button {
+                    class: "bg-sky-300 hover:bg-sky-500 text-white px-2 rounded mr-2",
+                    onclick: move |_| drafts.with_mut(|drafts| drafts.1.push(Draft::default())),
+                    "添加条件"
+                }
+                select {
+                    class: "{input}",
+                    onchange: move |e: Event<FormData>| {
+                        let join = if e.value() == "or" { td::Join::Or } else { td::Join::And };
+                        drafts.with_mut(|drafts| drafts.0 = join);
+                    },
+                    option { value: "and", selected: drafts.read().0 == td::Join::And, "全部满足 (and)" }
+                    option { value: "or", selected: drafts.read().0 == td::Join::Or, "任一满足 (or)" }
+                }
+                button {
+                    class: "bg-sky-500 hover:bg-sky-700 text-white font-bold px-4 rounded mr-2",
+                    onclick: {
+                        let on_search = props.on_search.clone();
+                        let loaded = loaded.clone();
+                        move |_| {
+                            let (join, list) = drafts.read().clone();
+                            match list.iter().map(|draft| draft.to_predicate(&loaded)).collect::<Result<Vec<td::Predicate>>>() {
+                                Ok(predicates) => {
+                                    error.set(String::new());
+                                    on_search.call((join, predicates));
+                                }
+                                Err(err) => error.set(format!("{:#}", err)),
+                            }
+                        }
+                    },
+                    "搜索"
+                }
+                button {
+                    class: "bg-gray-300 hover:bg-gray-500 text-white px-2 rounded",
+                    onclick: {
+                        let on_search = props.on_search.clone();
+                        move |_| {
+                            error.set(String::new());
+                            drafts.with_mut(|drafts| drafts.1.clear());
+                            on_search.call((td::Join::And, Vec::new()));
+                        }
+                    },
+                    "清除"
+                }
+                if !error.read().is_empty() {
+                    p { class: "text-red-500 ml-2", "{error}" }
                 }
             }
         }
@@ -360,43 +1049,162 @@ fn Table(props: TableList) -> Element {
 struct StablesList {
     width: i64,
     stables: Vec<String>,
+    current_host: String,
+    // 各主机的连接状态和隧道本地端口
+    hosts: Vec<HostSummary>,
+    // 当前主机的数据库和正在浏览的数据库
+    databases: Vec<td::Database>,
+    current_db: String,
+    // 切换数据库时读取超表失败的原因
+    stables_error: String,
     on_stable_change: EventHandler<String>,
     on_host_change: EventHandler<String>,
+    on_db_change: EventHandler<String>,
+    on_manage: EventHandler,
+    on_vault: EventHandler,
+    // 每次刷新加一, 用于让子表缓存失效
+    refresh: Signal<u64>,
 }
 
 #[allow(non_snake_case)]
 #[component]
 fn Stables(props: StablesList) -> Element {
+    // 按主机、数据库和超表缓存子表, 同时记录加载时的刷新次数
+    let sub_tables: Cache<Vec<String>> = use_signal(std::collections::HashMap::new);
+    // 按主机、数据库和搜索词缓存搜索结果
+    let found: Cache<Vec<(String, String)>> = use_signal(std::collections::HashMap::new);
+    let mut expanded = use_signal(std::collections::HashSet::<CacheKey>::new);
+    let mut search = use_signal(String::new);
+    let keyword = search.read().trim().to_string();
+    let lower = keyword.to_lowercase();
+    let key = |stable: &str| (props.current_host.clone(), props.current_db.clone(), stable.to_string());
+    // 超表按名称在本地过滤, 子表在服务端搜索
+    let tree = props
+        .stables
+        .iter()
+        .filter(|stable| stable.to_lowercase().contains(&lower))
+        .map(|stable| (stable.clone(), expanded.read().contains(&key(stable))))
+        .collect::<Vec<(String, bool)>>();
+    let toggle = {
+        let host = props.current_host.clone();
+        let db = props.current_db.clone();
+        move |stable: String| {
+            let key = (host.clone(), db.clone(), stable);
+            if !expanded.write().remove(&key) {
+                expanded.write().insert(key);
+            }
+        }
+    };
+    let current = CURRENT_STABLE.lock().unwrap().get_mut().clone();
     let f = |c: String| {
-        if c == CURRENT_STABLE.lock().unwrap().get_mut().clone() {
+        if c == current {
             "border-b  cursor-pointer hover:bg-gray-200 p-2 border-l-4 border-sky-500 ..."
                 .to_string()
         } else {
             "border-b  cursor-pointer hover:bg-gray-200 p-2 ".to_string()
         }
     };
+    let sources = CONF.lock().unwrap().sources.clone();
+    // 显示连接状态, ssh 数据源显示本地端口到远端的映射
+    let host_text = |conf: &Source| {
+        let host = props.hosts.iter().find(|host| host.name == conf.label());
+        match host.map(|host| (&host.state, host.local_port)) {
+            Some((HostState::Connecting, _)) => format!("{}  (连接中)", conf.label()),
+            Some((HostState::Failed(_), _)) => format!("{}  (连接失败)", conf.label()),
+            Some((_, Some(port))) => format!("{}  127.0.0.1:{} → {}:{}", conf.label(), port, conf.ip, conf.port),
+            _ => conf.label(),
+        }
+    };
     rsx! {
         div {
             style: "width:{props.width}px",
-            select {
-                class: "form-select w-full",
-                onchange: {
-                    let on_host_change = props.on_host_change.clone();
-                    move |e: Event<FormData>| {
-                        on_host_change.call(e.value());
+            div {
+                class: "flex",
+                select {
+                    class: "form-select w-full",
+                    onchange: {
+                        let on_host_change = props.on_host_change.clone();
+                        move |e: Event<FormData>| {
+                            on_host_change.call(e.value());
+                        }
+                    },
+                    for conf in sources.iter() {
+                        option {
+                            value: "{conf.label()}",
+                            selected: conf.label() == props.current_host,
+                            "{host_text(conf)}"
+                        }
                     }
-                },
-                for conf in CONF.sources.iter() {
-                    option {
-                         "{conf.ip.clone()}"
+                }
+                button {
+                    class: "ml-1 bg-sky-500 hover:bg-sky-700 text-white px-2 rounded whitespace-nowrap",
+                    onclick: {
+                        let on_manage = props.on_manage.clone();
+                        move |_| on_manage.call(())
+                    },
+                    "管理"
+                }
+                button {
+                    class: "ml-1 bg-sky-500 hover:bg-sky-700 text-white px-2 rounded whitespace-nowrap",
+                    onclick: {
+                        let on_vault = props.on_vault.clone();
+                        move |_| on_vault.call(())
+                    },
+                    "密钥"
+                }
+            }
+            // 当前主机的服务端版本
+            if let Some(version) = props.hosts.iter().find(|host| host.name == props.current_host).and_then(|host| host.version.clone()) {
+                p { class: "text-gray-400 text-sm px-1", "TDengine {version}" }
+            }
+            if !props.databases.is_empty() {
+                select {
+                    class: "form-select w-full mt-1",
+                    onchange: {
+                        let on_db_change = props.on_db_change.clone();
+                        move |e: Event<FormData>| {
+                            on_db_change.call(e.value());
+                        }
+                    },
+                    for db in props.databases.iter() {
+                        option {
+                            value: "{db.name}",
+                            selected: db.name == props.current_db,
+                            "{db.name}"
+                        }
                     }
                 }
             }
+            // 当前数据库的参数
+            if let Some(db) = props.databases.iter().find(|db| db.name == props.current_db) {
+                p {
+                    class: "text-gray-400 text-sm px-1",
+                    "precision {db.precision}  keep {db.keep}  vgroups {db.vgroups}  replica {db.replica}"
+                }
+            }
+            input {
+                class: "form-input w-full mt-1",
+                placeholder: "搜索超表和子表",
+                value: "{search}",
+                oninput: move |e: Event<FormData>| search.set(e.value()),
+            }
+            if !props.stables_error.is_empty() {
+                p { class: "text-red-500 text-sm p-1 whitespace-pre-wrap", "读取超表失败: {props.stables_error}" }
+            }
             div {
                 class: "list-none border",
-                for stable in props.stables.iter() {
+                for (stable, open) in tree.into_iter() {
                     div {
                         class: "flex w-full",
+                        span {
+                            class: "border-b cursor-pointer hover:bg-gray-200 p-2 text-gray-400",
+                            onclick: {
+                                let stable = stable.clone();
+                                let mut toggle = toggle.clone();
+                                move |_evt| toggle(stable.clone())
+                            },
+                            if open { "▾" } else { "▸" }
+                        }
                         li {
                             class: "{f(stable.clone())} text-gray-600 w-full",
                             onclick: {
@@ -409,6 +1217,94 @@ fn Stables(props: StablesList) -> Element {
                             " {stable}"
                         }
                     }
+                    if open {
+                        // 切换主机、数据库或超表时重新挂载, 由 effect 加载子表
+                        SubTableList {
+                            key: "{props.current_host}/{props.current_db}/{stable}",
+                            props: SubTableItems {
+                                stable: key(&stable),
+                                sub_tables,
+                                refresh: props.refresh,
+                                current: current.clone(),
+                                on_stable_change: props.on_stable_change.clone(),
+                            },
+                        }
+                    }
+                }
+            }
+            if !keyword.is_empty() {
+                // 搜索词变化时重新挂载, 在服务端按名称搜索整个库的子表
+                SubTableSearch {
+                    key: "{props.current_host}/{props.current_db}/{keyword}",
+                    props: SubTableMatches {
+                        search: key(&keyword),
+                        found,
+                        refresh: props.refresh,
+                        current: current.clone(),
+                        on_stable_change: props.on_stable_change.clone(),
+                    },
+                }
+            }
+        }
+    }
+}
+
+#[derive(Props, PartialEq, Clone)]
+struct TagList {
+    width: i64,
+    current_host: String,
+    current_db: String,
+    stable: String,
+    // 当前生效的标签过滤
+    filters: Vec<td::TagFilter>,
+    on_filter: EventHandler<Vec<td::TagFilter>>,
+    // 打开子表的数据
+    on_open: EventHandler<String>,
+    // 每次刷新加一, 用于让缓存失效
+    refresh: Signal<u64>,
+}
+
+#[allow(non_snake_case)]
+#[component]
+fn Tags(props: TagList) -> Element {
+    let mut open = use_signal(|| false);
+    // 按主机、数据库和超表缓存标签, 同时记录加载时的刷新次数
+    let tags: Cache<td::TagTable> = use_signal(std::collections::HashMap::new);
+    rsx! {
+        div {
+            style: "width:{props.width}px",
+            class: "mb-1",
+            div {
+                class: "flex items-center",
+                button {
+                    class: "bg-sky-300 hover:bg-sky-500 text-white px-2 rounded mr-2",
+                    onclick: move |_| open.set(!open()),
+                    if open() { "收起标签" } else { "标签" }
+                }
+                for filter in props.filters.iter() {
+                    span {
+                        class: "bg-sky-100 text-sky-700 rounded px-2 mr-1 cursor-pointer",
+                        onclick: {
+                            let on_filter = props.on_filter.clone();
+                            let filters = props.filters.iter().filter(|f| f.tag != filter.tag).cloned().collect::<Vec<td::TagFilter>>();
+                            move |_| on_filter.call(filters.clone())
+                        },
+                        "{filter.tag.name} = {filter.values.join(\" | \")}  ×"
+                    }
+                }
+            }
+            if open() {
+                // 切换超表时重新挂载, 由 effect 加载新超表的标签
+                TagGrid {
+                    key: "{props.current_host}/{props.current_db}/{props.stable}",
+                    props: TagGridList {
+                        table: (props.current_host.clone(), props.current_db.clone(), props.stable.clone()),
+                        tags,
+                        refresh: props.refresh,
+                        filters: props.filters.clone(),
+                        on_filter: props.on_filter.clone(),
+                        on_open: props.on_open.clone(),
+                    },
                 }
             }
         }
@@ -417,7 +1313,7 @@ fn Stables(props: StablesList) -> Element {
 
 // connect to taos
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct TableData {
     headers: Vec<String>,
     rows: Vec<Vec<String>>,
@@ -427,77 +1323,275 @@ pub struct TableData {
     real_moving_size: Vec<i64>,
     widths: Vec<i64>,
     spend: String,
+    query: QueryState,
+}
+
+// 数据查询的状态, 查询中时显示取消按钮
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum QueryState {
+    #[default]
+    Done,
+    Running,
+    Interrupted(td::Interrupted),
+    // 查询出错时保留上一次的数据并显示错误
+    Failed(String),
+}
+
+// 主机连接状态, 选中时才开始连接
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum HostState {
+    #[default]
+    Idle,
+    Connecting,
+    Connected,
+    Failed(String),
+}
+
+// 主机列表中显示的主机信息
+#[derive(Clone, PartialEq)]
+pub struct HostSummary {
+    name: String,
+    state: HostState,
+    local_port: Option<u16>,
+    // 已连接时的服务端版本
+    version: Option<String>,
 }
 
 pub struct HostData {
+    name: String,
+    state: HostState,
     ip: String,
     port: usize,
-    local_port: Option<usize>,
-    ssh_user: Option<String>,
-    password: Option<String>,
-    taos: Option<Taos>,
+    protocol: Protocol,
+    token: Option<String>,
+    // 随主机一起释放, 断开或重连时关闭旧隧道
+    tunnel: Option<Tunnel>,
+    // 不经 ssh 直连时的代理转发
+    relay: Option<proxy::Relay>,
+    // 隧道连续重连失败的次数和下次重连的时间
+    failures: u32,
+    retry_at: Option<std::time::Instant>,
+    user: Option<String>,
+    db_password: Option<String>,
+    // 查询时复制一份连接池, 不持锁等待
+    pool: Option<TaosPool>,
+    // 连接时检测的服务端版本
+    server: td::Server,
+    // 主机上的所有数据库, db 为正在浏览的数据库
+    databases: Vec<td::Database>,
+    query_timeout: Option<std::time::Duration>,
+    time_display: td::TimeDisplay,
     db: String,
     stables: Vec<String>,
+    // 切换数据库时读取超表失败的原因, 显示在超表列表中
+    stables_error: String,
+    // 创建主机时的数据源, 连接完成时据此判断数据源是否已改动
+    source: Source,
+}
+
+impl HostData {
+    // 未连接的主机
+    fn new(config: Source) -> HostData {
+        // 配置加载时已校验
+        let time_display = config.time_display().unwrap_or_default();
+        let source = config.clone();
+        HostData {
+            name: config.label(),
+            state: HostState::Idle,
+            ip: config.ip,
+            port: config.port,
+            protocol: config.protocol.unwrap_or_default(),
+            token: None,
+            tunnel: None,
+            relay: None,
+            failures: 0,
+            retry_at: None,
+            user: config.user,
+            db_password: None,
+            db: config.db,
+            pool: None,
+            server: td::Server::default(),
+            databases: Vec::new(),
+            query_timeout: config.query_timeout.map(std::time::Duration::from_secs),
+            time_display,
+            stables: Vec::new(),
+            stables_error: String::new(),
+            source,
+        }
+    }
+
+    // 重建主机时保留正在浏览的数据库, 数据源指向别的服务端时不保留
+    fn keep_selection(&mut self, old: &HostData) {
+        if old.ip == self.ip && old.port == self.port {
+            self.db = old.db.clone();
+            self.databases = old.databases.clone();
+        }
+    }
+
+    // 解析数据库密码和 token, 连接前调用
+    fn with_secrets(config: Source) -> Result<HostData> {
+        let db_password = config.password.as_ref().map(Secret::resolve).transpose()?;
+        let token = config.token.as_ref().map(Secret::resolve).transpose()?;
+        let mut host_data = HostData::new(config);
+        host_data.db_password = db_password;
+        host_data.token = token;
+        Ok(host_data)
+    }
+
+    // TDengine 的连接地址, 经隧道或代理时为本地转发端口
+    fn address(&self) -> taos_query::Address {
+        match (&self.tunnel, &self.relay) {
+            (Some(tunnel), _) => taos_query::Address::new("127.0.0.1", tunnel.local_port),
+            (None, Some(relay)) => taos_query::Address::new("127.0.0.1", relay.local_port),
+            (None, None) => taos_query::Address::new(self.ip.clone(), self.port as u16),
+        }
+    }
+
+    fn dsn(&self) -> Dsn {
+        let driver = match self.protocol {
+            Protocol::Native => "taos",
+            Protocol::Ws => "ws",
+            // 本地转发已完成 TLS
+            Protocol::Wss if self.relay.as_ref().is_some_and(|relay| relay.tls) => "ws",
+            Protocol::Wss => "wss",
+        };
+        let mut params = std::collections::BTreeMap::new();
+        if let Some(token) = &self.token {
+            params.insert("token".to_string(), token.clone());
+        }
+        // 直接构造 Dsn, 密码中的特殊字符无需转义
+        Dsn {
+            driver: driver.to_string(),
+            username: self.user.clone(),
+            password: self.db_password.clone(),
+            addresses: vec![self.address()],
+            params,
+            // 池中每个连接都直接使用该数据库
+            subject: Some(self.db.clone()),
+            ..Default::default()
+        }
+    }
+}
+
+// 连接数据源, db 为连接后浏览的数据库
+async fn connect_host(config: Source, db: String) -> Result<HostData> {
+    let name = config.label();
+    let tunnel_config = tunnel_config(&config)?;
+    let proxy = proxy_config(&config)?;
+    let tls = tls_config(&config)?;
+    let mut host_data = HostData::with_secrets(config)?;
+    host_data.db = db;
+    // 开启ssh隧道, 隧道就绪后再连接 TDengine
+    if let Some(tunnel_config) = tunnel_config {
+        let tunnel = tokio::task::spawn_blocking(move || tunnel::open(tunnel_config))
+            .await?
+            .with_context(|| format!("{}: failed to open ssh tunnel", name))?;
+        host_data.tunnel = Some(tunnel);
+    } else if proxy.is_some() || tls.is_some() {
+        // 连接器不支持代理和自定义证书, 经本地端口转发
+        let relay = proxy::relay(proxy, (host_data.ip.clone(), host_data.port as u16), tls)
+            .with_context(|| format!("{}: failed to start local relay", name))?;
+        host_data.relay = Some(relay);
+    }
+
+    connect_taos(&mut host_data)
+        .await
+        .with_context(|| format!("{}: failed to connect to TDengine", name))?;
+    host_data.state = HostState::Connected;
+    Ok(host_data)
 }
 
-async fn connect_host(config: Source) -> HostData {
-    let mut host_data = HostData {
-        ip: config.ip,
-        port: config.port,
-        local_port: config.local_port, // ssh -nl 本地端口
-        ssh_user: config.ssh_user,
-        password: config.ssh_password,
-        db: config.db,
-        taos: None,
-        stables: Vec::new(),
+fn tunnel_config(config: &Source) -> Result<Option<TunnelConfig>> {
+    let Some(user) = config.ssh_user.clone() else {
+        return Ok(None);
     };
+    // 先经过跳板机, 最后一跳是数据源所在的主机
+    let mut hops = Vec::new();
+    for jump in config.jump.iter() {
+        hops.push(Hop {
+            host: jump.ip.clone(),
+            port: jump.port.unwrap_or(22) as u16,
+            user: jump.user.clone(),
+            auth: ssh_auth(&jump.password, &jump.key, &jump.key_passphrase)?,
+        });
+    }
+    hops.push(Hop {
+        host: config.ip.clone(),
+        port: config.ssh_port.unwrap_or(22) as u16,
+        user,
+        auth: ssh_auth(&config.ssh_password, &config.ssh_key, &config.ssh_key_passphrase)?,
+    });
+    Ok(Some(TunnelConfig {
+        hops,
+        target_port: config.port as u16,
+        local_port: config.local_port.map(|port| port as u16),
+        proxy: proxy_config(config)?,
+    }))
+}
 
-    // 开启ssh隧道
-    if host_data.ssh_user.is_some() {
-        let mut command = std::process::Command::new("/usr/bin/ssh");
-        command.args([
-            "-NL",
-            format!(
-                "{}:127.0.0.1:{}",
-                host_data.local_port.unwrap(),
-                host_data.port
-            )
-                .as_str(),
-            format!("{}@{}", host_data.ssh_user.clone().unwrap(), host_data.ip).as_str(),
-        ]);
-        command.spawn().unwrap();
-    }
-    connect_taos(&mut host_data).await;
-    host_data
-}
-
-async fn connect_taos(host_data: &mut HostData) {
-    let dsn: String;
-    if host_data.ssh_user.is_some() {
-        dsn = format!("taos://127.0.0.1:{}", host_data.local_port.unwrap());
-    } else {
-        dsn = format!("taos://{}:6030", host_data.ip);
+fn proxy_config(config: &Source) -> Result<Option<proxy::Proxy>> {
+    let Some(url) = &config.proxy else {
+        return Ok(None);
+    };
+    let mut proxy = proxy::Proxy::parse(url)?;
+    proxy.password = config.proxy_password.as_ref().map(Secret::resolve).transpose()?;
+    Ok(Some(proxy))
+}
+
+// wss 配置了自定义 CA、跳过校验或经代理时由本地转发完成 TLS, 证书按 ip 校验
+fn tls_config(config: &Source) -> Result<Option<tls::Tls>> {
+    let insecure = config.insecure.unwrap_or_default();
+    let relayed = config.ca_path.is_some() || insecure || (config.proxy.is_some() && config.ssh_user.is_none());
+    if config.protocol != Some(Protocol::Wss) || !relayed {
+        return Ok(None);
     }
-    let builder = TaosBuilder::from_dsn(dsn).unwrap();
-    let taos = builder.build().await.unwrap();
-    taos.use_database(&host_data.db).await.unwrap();
-    let stables = td::STable::get_stables(&taos).await.unwrap();
+    Ok(Some(tls::Tls::new(&config.ip, config.ca_path.as_deref(), insecure)?))
+}
+
+fn ssh_auth(
+    password: &Option<Secret>,
+    key: &Option<std::path::PathBuf>,
+    passphrase: &Option<Secret>,
+) -> Result<SshAuth> {
+    Ok(match (password, key) {
+        (Some(password), _) => SshAuth::Password(password.resolve()?),
+        (None, Some(path)) => SshAuth::Key {
+            path: path.clone(),
+            passphrase: passphrase.as_ref().map(Secret::resolve).transpose()?,
+        },
+        (None, None) => SshAuth::Agent,
+    })
+}
+
+async fn connect_taos(host_data: &mut HostData) -> Result<()> {
+    let dsn = host_data.dsn();
+    let pool = TaosBuilder::from_dsn(dsn)?
+        .pool_builder()
+        .max_size(POOL_SIZE)
+        .build()?;
+    let taos = pool.get().await?;
+    let server = td::Server::detect(&taos).await?;
+    let databases = server.databases(&taos).await?;
+    let stables = td::STable::get_stables(&taos, &server, &host_data.db).await?;
     let stables = stables
         .iter()
         .map(|item| item.stable_name.clone())
         .collect::<Vec<String>>();
 
     host_data.stables = stables;
-    host_data.taos = Some(taos);
+    host_data.server = server;
+    host_data.databases = databases;
+    host_data.pool = Some(pool);
+    Ok(())
 }
 
-fn turn_taos(ip: String) {
+fn turn_taos(name: String) {
     {
         let mut index = 0;
         let mut taos = TAOS.try_lock().unwrap();
         let mut taos_data = taos.take().unwrap();
         for (i, each) in taos_data.iter().enumerate() {
-            if each.ip == ip {
+            if each.name == name {
                 index = i;
                 break;
             }
@@ -508,14 +1602,289 @@ fn turn_taos(ip: String) {
     print_current_host();
 }
 
-fn get_stables() -> Vec<String> {
+// 开始连接未连接或连接失败的主机, 返回其数据源和正在浏览的数据库; 已连接或正在连接时返回 None
+fn begin_connect(name: &str) -> Option<(Source, String)> {
+    let mut taos = TAOS.lock().unwrap();
+    let host = taos.get_mut()?.iter_mut().find(|host| host.name == name)?;
+    if !matches!(host.state, HostState::Idle | HostState::Failed(_)) || !claim_connect(host) {
+        return None;
+    }
+    Some((host.source.clone(), host.db.clone()))
+}
+
+// 连接中的状态即进行中的标记, 手动重试和自动重连都经这里占用, 不会同时为一个主机建立两条隧道
+fn claim_connect(host: &mut HostData) -> bool {
+    if host.state == HostState::Connecting {
+        return false;
+    }
+    host.state = HostState::Connecting;
+    true
+}
+
+// 仍在用同一数据源连接的主机, 连接期间主机被删除、改动或已由别处连接时为 None
+fn connecting_host<'a>(hosts: &'a mut [HostData], name: &str, source: &Source) -> Option<&'a mut HostData> {
+    hosts
+        .iter_mut()
+        .find(|host| host.name == name)
+        .filter(|host| host.state == HostState::Connecting && host.source == *source)
+}
+
+// 记录连接结果, 连接期间主机被删除或改动时丢弃
+fn finish_connect(name: &str, source: &Source, result: Result<HostData>) {
+    let mut taos = TAOS.lock().unwrap();
+    let Some(host) = taos.get_mut().and_then(|hosts| connecting_host(hosts, name, source)) else {
+        return;
+    };
+    match result {
+        Ok(new_host) => *host = new_host,
+        Err(err) => host.state = HostState::Failed(format!("{:#}", err)),
+    }
+}
+
+// 应用新配置: 断开删除的数据源, 新增和变化的数据源换成未连接的主机, 选中时再连接.
+// 返回当前主机是否受影响
+fn apply_config(config: config::Config) -> bool {
+    let old = std::mem::replace(&mut *CONF.lock().unwrap(), config.clone());
+    let diff = config::diff(&old.sources, &config.sources);
+    let affected = diff.affects(&current_host());
+
+    let mut taos = TAOS.lock().unwrap();
+    let Some(hosts) = taos.get_mut() else {
+        return false;
+    };
+    hosts.retain(|host| !diff.removed.contains(&host.name));
+    for source in diff.changed {
+        let mut host = HostData::new(source);
+        match hosts.iter_mut().find(|each| each.name == host.name) {
+            Some(each) => {
+                host.keep_selection(each);
+                *each = host;
+            }
+            None => hosts.push(host),
+        }
+    }
+    for source in diff.added {
+        hosts.push(HostData::new(source));
+    }
+    affected
+}
+
+// 检查已连接的隧道并重连断开的, 返回 None 表示没有变化, Some(true) 表示当前主机有变化
+async fn supervise() -> Option<bool> {
+    let checks = {
+        let taos = TAOS.lock().unwrap();
+        let now = std::time::Instant::now();
+        taos.get()?
+            .iter()
+            .filter(|host| host.state != HostState::Connecting)
+            .filter_map(|host| match (host.retry_at, &host.tunnel) {
+                (Some(at), _) if now >= at => Some((host.name.clone(), None)),
+                (None, Some(tunnel)) => Some((host.name.clone(), Some(tunnel.probe()))),
+                _ => None,
+            })
+            .collect::<Vec<(String, Option<tunnel::Probe>)>>()
+    };
+
+    let mut changed = None;
+    for (name, probe) in checks {
+        if let Some(probe) = probe {
+            if let Ok(Ok(())) = tokio::task::spawn_blocking(move || probe.check()).await {
+                continue;
+            }
+        }
+        // 检查期间已开始手动重试时跳过; 先关闭旧隧道, 记下数据源和正在浏览的数据库
+        let old = TAOS
+            .lock()
+            .unwrap()
+            .get_mut()
+            .and_then(|hosts| hosts.iter_mut().find(|host| host.name == name))
+            .and_then(|host| claim_connect(host).then(|| (host.tunnel.take(), host.source.clone(), host.db.clone())));
+        let Some((tunnel, source, db)) = old else {
+            continue;
+        };
+        drop(tunnel);
+
+        let result = connect_host(source.clone(), db).await;
+        let mut taos = TAOS.lock().unwrap();
+        let Some(hosts) = taos.get_mut() else {
+            break;
+        };
+        let is_current = hosts.first().is_some_and(|host| host.name == name);
+        // 连接期间数据源被改动或删除时丢弃结果
+        let Some(host) = connecting_host(hosts, &name, &source) else {
+            continue;
+        };
+        match result {
+            Ok(new_host) => *host = new_host,
+            Err(err) => {
+                host.failures += 1;
+                let delay = RETRY_MIN
+                    .saturating_mul(1 << (host.failures - 1).min(16))
+                    .min(RETRY_MAX);
+                host.retry_at = Some(std::time::Instant::now() + delay);
+                host.state = HostState::Failed(format!("{:#}\nretry in {}s", err, delay.as_secs()));
+            }
+        }
+        changed = Some(changed.unwrap_or(false) || is_current);
+    }
+    changed
+}
+
+// 退出时断开所有主机, 关闭隧道
+fn shutdown_hosts() {
+    drop(TAOS.lock().unwrap().take());
+}
+
+fn current_host() -> String {
+    TAOS.lock()
+        .unwrap()
+        .get()
+        .and_then(|hosts| hosts.first())
+        .map(|host| host.name.clone())
+        .unwrap_or_default()
+}
+
+fn current_state() -> HostState {
+    TAOS.lock()
+        .unwrap()
+        .get()
+        .and_then(|hosts| hosts.first())
+        .map(|host| host.state.clone())
+        .unwrap_or_default()
+}
+
+fn current_features() -> td::Features {
+    TAOS.lock()
+        .unwrap()
+        .get()
+        .and_then(|hosts| hosts.first())
+        .map(|host| host.server.features())
+        .unwrap_or_default()
+}
+
+fn host_summaries() -> Vec<HostSummary> {
+    TAOS.lock()
+        .unwrap()
+        .get()
+        .map(|hosts| {
+            hosts
+                .iter()
+                .map(|host| HostSummary {
+                    name: host.name.clone(),
+                    state: host.state.clone(),
+                    local_port: host.tunnel.as_ref().map(|tunnel| tunnel.local_port),
+                    version: host.pool.as_ref().map(|_| host.server.version.clone()),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn current_databases() -> (Vec<td::Database>, String, String) {
     TAOS.lock()
         .unwrap()
+        .get()
+        .and_then(|hosts| hosts.first())
+        .map(|host| (host.databases.clone(), host.db.clone(), host.stables_error.clone()))
+        .unwrap_or_default()
+}
+
+// 切换当前主机正在浏览的数据库, 查询出新库的超表后再切换
+async fn select_database(db: String) -> Result<()> {
+    let host = TAOS
+        .lock()
+        .unwrap()
+        .get()
+        .and_then(|hosts| hosts.first())
+        .and_then(|host| Some((host.name.clone(), host.pool.clone()?, host.server.clone())));
+    let Some((name, pool, server)) = host else {
+        return Ok(());
+    };
+    let stables = async {
+        let taos = pool.get().await?;
+        td::STable::get_stables(&taos, &server, &db).await
+    }
+    .await
+    .map(|stables| stables.into_iter().map(|item| item.stable_name).collect::<Vec<String>>());
+
+    // 失败时也切到所选的库并清空上一个库的超表, 不再显示和查询旧库的表
+    let mut taos = TAOS.lock().unwrap();
+    if let Some(host) = taos
         .get_mut()
+        .and_then(|hosts| hosts.iter_mut().find(|host| host.name == name))
+    {
+        host.db = db;
+        host.stables_error = stables.as_ref().err().map(|err| format!("{:#}", err)).unwrap_or_default();
+        host.stables = stables.as_ref().cloned().unwrap_or_default();
+    }
+    stables.map(|_| ())
+}
+
+// 取出主机的连接池和版本后释放锁, 再从池中取一个连接交给 f
+async fn with_pool<T>(
+    name: &str,
+    f: impl for<'a> FnOnce(&'a Taos, &'a td::Server) -> Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>,
+) -> Result<T> {
+    let host = TAOS
+        .lock()
         .unwrap()
-        .index(0)
-        .stables
-        .clone()
+        .get()
+        .and_then(|hosts| hosts.iter().find(|host| host.name == name))
+        .and_then(|host| Some((host.pool.clone()?, host.server.clone())));
+    let (pool, server) = host.with_context(|| format!("{} is not connected", name))?;
+    let taos = pool.get().await?;
+    f(&taos, &server).await
+}
+
+// 查询指定主机和数据库中超表的子表, 最多 limit 个
+async fn get_sub_tables(name: String, db: String, stable: String, limit: usize) -> Result<Vec<String>> {
+    with_pool(&name, |taos, server| Box::pin(async move {
+        let tables = td::STable::new(db, stable)
+            .get_sub_tables(taos, server, limit)
+            .await?
+            .into_iter()
+            .map(|table| table.table_name)
+            .collect();
+        Ok(tables)
+    }))
+    .await
+}
+
+// 在指定主机和数据库中按名称搜索子表, 返回 (超表, 子表), 最多 limit 个
+async fn search_sub_tables(name: String, db: String, keyword: String, limit: usize) -> Result<Vec<(String, String)>> {
+    with_pool(&name, |taos, server| Box::pin(async move {
+        let tables = td::STable::search_sub_tables(taos, server, &db, &keyword, limit)
+            .await?
+            .into_iter()
+            .map(|(stable, table)| (stable, table.table_name))
+            .collect();
+        Ok(tables)
+    }))
+    .await
+}
+
+// 查询指定主机和数据库中超表的标签和子表的标签值
+async fn get_tags(name: String, db: String, stable: String) -> Result<td::TagTable> {
+    with_pool(&name, |taos, server| Box::pin(async move { td::STable::new(db, stable).get_tags(taos, server).await })).await
+}
+
+// 查询指定主机和数据库中表的列和标签
+async fn get_columns(name: String, db: String, table: String) -> Result<Vec<td::Column>> {
+    with_pool(&name, |taos, _| Box::pin(async move { td::STable::new(db, table).get_columns(taos).await })).await
+}
+
+// 查询指定主机和数据库中表的结构和建表语句
+async fn get_schema(name: String, db: String, table: String, stable: bool) -> Result<td::Schema> {
+    with_pool(&name, |taos, server| Box::pin(async move { td::STable::new(db, table).get_schema(taos, server, stable).await })).await
+}
+
+fn get_stables() -> Vec<String> {
+    TAOS.lock()
+        .unwrap()
+        .get()
+        .and_then(|hosts| hosts.first())
+        .map(|host| host.stables.clone())
+        .unwrap_or_default()
 }
 
 fn print_current_host() {}
@@ -0,0 +1,354 @@
+use std::path::PathBuf;
+
+use dioxus::prelude::*;
+
+use crate::config::{self, Config, JumpHost, Protocol, Secret, Source, SourceEntry, CONF, CONF_PATH};
+use crate::diagnose::{self, Step};
+
+#[derive(Props, Clone, PartialEq)]
+pub struct SourceList {
+    pub on_close: EventHandler,
+    pub on_saved: EventHandler<Config>,
+}
+
+// 连接管理: 新建、编辑、复制、删除、排序数据源并写回配置文件
+#[allow(non_snake_case)]
+#[component]
+pub fn SourceManager(props: SourceList) -> Element {
+    let mut entries = use_signal(|| {
+        CONF.lock()
+            .unwrap()
+            .sources
+            .iter()
+            .enumerate()
+            .map(|(index, source)| SourceEntry {
+                origin: Some(index),
+                source: source.clone(),
+            })
+            .collect::<Vec<SourceEntry>>()
+    });
+    let mut selected = use_signal(|| 0usize);
+    let mut error = use_signal(String::new);
+    // 测试连接的结果, 切换数据源时清空
+    let mut testing = use_signal(|| false);
+    let mut diagnosis = use_signal(Vec::<Step>::new);
+
+    let current = selected();
+    let source = entries.read().get(current).map(|entry| entry.source.clone());
+
+    let item_class = |index: usize| {
+        if index == current {
+            "border-b cursor-pointer hover:bg-gray-200 p-2 border-l-4 border-sky-500"
+        } else {
+            "border-b cursor-pointer hover:bg-gray-200 p-2"
+        }
+    };
+
+    rsx! {
+        div {
+            class: "fixed inset-0 bg-gray-500/50 flex justify-center items-center",
+            div {
+                class: "bg-white rounded-md shadow-lg p-4 flex flex-col",
+                style: "width:1000px;height:700px",
+                div { class: "flex mb-2",
+                    p { class: "text-lg font-bold text-gray-600", "连接管理" }
+                    button {
+                        class: "ml-auto text-gray-400 hover:text-gray-600",
+                        onclick: {
+                            let on_close = props.on_close.clone();
+                            move |_| on_close.call(())
+                        },
+                        "关闭"
+                    }
+                }
+                div { class: "flex flex-1 overflow-hidden",
+                    div { class: "w-1/3 flex flex-col mr-4",
+                        div { class: "list-none border flex-1 overflow-auto",
+                            for (index , entry) in entries.read().iter().enumerate() {
+                                li {
+                                    class: "{item_class(index)} text-gray-600",
+                                    onclick: move |_| {
+                                        selected.set(index);
+                                        diagnosis.set(Vec::new());
+                                    },
+                                    "{entry.source.label()}"
+                                }
+                            }
+                        }
+                        div { class: "flex flex-wrap mt-2 gap-1",
+                            button {
+                                class: "bg-sky-500 hover:bg-sky-700 text-white py-1 px-2 rounded",
+                                onclick: move |_| {
+                                    entries.with_mut(|entries| {
+                                        entries.push(SourceEntry {
+                                            origin: None,
+                                            source: Source {
+                                                port: 6030,
+                                                ..Default::default()
+                                            },
+                                        });
+                                        selected.set(entries.len() - 1);
+                                    });
+                                },
+                                "新建"
+                            }
+                            button {
+                                class: "bg-sky-500 hover:bg-sky-700 text-white py-1 px-2 rounded",
+                                onclick: move |_| {
+                                    entries.with_mut(|entries| {
+                                        if let Some(entry) = entries.get(current).cloned() {
+                                            let mut entry = entry;
+                                            entry.source.name = Some(format!("{} copy", entry.source.label()));
+                                            entries.insert(current + 1, entry);
+                                            selected.set(current + 1);
+                                        }
+                                    });
+                                },
+                                "复制"
+                            }
+                            button {
+                                class: "bg-sky-500 hover:bg-sky-700 text-white py-1 px-2 rounded",
+                                onclick: move |_| {
+                                    if current > 0 {
+                                        entries.with_mut(|entries| entries.swap(current, current - 1));
+                                        selected.set(current - 1);
+                                    }
+                                },
+                                "上移"
+                            }
+                            button {
+                                class: "bg-sky-500 hover:bg-sky-700 text-white py-1 px-2 rounded",
+                                onclick: move |_| {
+                                    if current + 1 < entries.read().len() {
+                                        entries.with_mut(|entries| entries.swap(current, current + 1));
+                                        selected.set(current + 1);
+                                    }
+                                },
+                                "下移"
+                            }
+                            button {
+                                class: "bg-red-500 hover:bg-red-700 text-white py-1 px-2 rounded",
+                                onclick: move |_| {
+                                    entries.with_mut(|entries| {
+                                        if current < entries.len() {
+                                            entries.remove(current);
+                                        }
+                                        selected.set(current.min(entries.len().saturating_sub(1)));
+                                    });
+                                },
+                                "删除"
+                            }
+                        }
+                    }
+                    div { class: "w-2/3 overflow-auto",
+                        if let Some(source) = source {
+                            {field("name", source.name.clone().unwrap_or_default(), move |v| edit(entries, current, move |s| s.name = optional(v)))}
+                            {field("ip", source.ip.clone(), move |v| edit(entries, current, move |s| s.ip = v.trim().to_string()))}
+                            {field("port", source.port.to_string(), move |v| edit(entries, current, move |s| s.port = v.trim().parse().unwrap_or(0)))}
+                            {protocol_field(entries, current, source.protocol.unwrap_or_default())}
+                            if source.protocol.unwrap_or_default() != Protocol::Native {
+                                {field("token", source.token.as_ref().map(Secret::to_text).unwrap_or_default(), move |v| edit(entries, current, move |s| s.token = Secret::from_text(v)))}
+                            }
+                            if source.protocol.unwrap_or_default() == Protocol::Wss {
+                                {field("ca_path", source.ca_path.as_ref().map(|p| p.display().to_string()).unwrap_or_default(), move |v| edit(entries, current, move |s| s.ca_path = optional(v).map(PathBuf::from)))}
+                                {field("insecure", source.insecure.map(|v| v.to_string()).unwrap_or_default(), move |v| edit(entries, current, move |s| s.insecure = optional(v).map(|v| v == "true")))}
+                            }
+                            {field("db", source.db.clone(), move |v| edit(entries, current, move |s| s.db = v.trim().to_string()))}
+                            {field("user", source.user.clone().unwrap_or_default(), move |v| edit(entries, current, move |s| s.user = optional(v)))}
+                            {field("password", source.password.as_ref().map(Secret::to_text).unwrap_or_default(), move |v| edit(entries, current, move |s| s.password = Secret::from_text(v)))}
+                            {field("query_timeout", source.query_timeout.map(|t| t.to_string()).unwrap_or_default(), move |v| edit(entries, current, move |s| s.query_timeout = optional(v).map(|t| t.parse().unwrap_or(0))))}
+                            {field("timezone", source.timezone.clone().unwrap_or_default(), move |v| edit(entries, current, move |s| s.timezone = optional(v)))}
+                            {field("locale", source.locale.clone().unwrap_or_default(), move |v| edit(entries, current, move |s| s.locale = optional(v)))}
+                            {field("timestamp_format", source.timestamp_format.clone().unwrap_or_default(), move |v| edit(entries, current, move |s| s.timestamp_format = optional(v)))}
+                            {field("ssh_user", source.ssh_user.clone().unwrap_or_default(), move |v| edit(entries, current, move |s| s.ssh_user = optional(v)))}
+                            {field("ssh_port", source.ssh_port.map(|p| p.to_string()).unwrap_or_default(), move |v| edit(entries, current, move |s| s.ssh_port = optional(v).map(|p| p.parse().unwrap_or(0))))}
+                            {field("ssh_password", source.ssh_password.as_ref().map(Secret::to_text).unwrap_or_default(), move |v| edit(entries, current, move |s| s.ssh_password = Secret::from_text(v)))}
+                            {field("ssh_key", source.ssh_key.as_ref().map(|p| p.display().to_string()).unwrap_or_default(), move |v| edit(entries, current, move |s| s.ssh_key = optional(v).map(PathBuf::from)))}
+                            {field("ssh_key_passphrase", source.ssh_key_passphrase.as_ref().map(Secret::to_text).unwrap_or_default(), move |v| edit(entries, current, move |s| s.ssh_key_passphrase = Secret::from_text(v)))}
+                            {field("local_port", source.local_port.map(|p| p.to_string()).unwrap_or_default(), move |v| edit(entries, current, move |s| s.local_port = optional(v).map(|p| p.parse().unwrap_or(0))))}
+                            {field("proxy", source.proxy.clone().unwrap_or_default(), move |v| edit(entries, current, move |s| s.proxy = optional(v)))}
+                            {field("proxy_password", source.proxy_password.as_ref().map(Secret::to_text).unwrap_or_default(), move |v| edit(entries, current, move |s| s.proxy_password = Secret::from_text(v)))}
+                            div { class: "flex items-center mb-2",
+                                p { class: "text-gray-600", "跳板机 (按顺序连接, 最后经 ssh_user@ip 转发)" }
+                                button {
+                                    class: "ml-auto bg-sky-500 hover:bg-sky-700 text-white py-1 px-2 rounded",
+                                    onclick: move |_| edit(entries, current, |s| s.jump.push(JumpHost::default())),
+                                    "添加跳板机"
+                                }
+                            }
+                            for (hop , jump) in source.jump.iter().enumerate() {
+                                {jump_fields(entries, current, hop, jump)}
+                            }
+                            p { class: "text-gray-400 text-sm", "密码填写 vault:名称 引用密钥库中的条目" }
+                            p { class: "text-gray-400 text-sm", "ssh 未填写密码和私钥时使用 ssh-agent 认证, local_port 留空时自动分配" }
+                            p { class: "text-gray-400 text-sm", "proxy 形如 socks5://user@host:1080 或 http://host:3128, ssh 和直连都经过代理" }
+                        } else {
+                            p { class: "text-gray-400", "没有数据源" }
+                        }
+                    }
+                }
+                if !error.read().is_empty() {
+                    pre { class: "text-red-500 whitespace-pre-wrap mt-2", "{error}" }
+                }
+                if !diagnosis.read().is_empty() {
+                    div { class: "border rounded-md p-2 mt-2 overflow-auto", style: "max-height:200px",
+                        for step in diagnosis.read().iter() {
+                            div { class: "flex text-sm",
+                                if step.error.is_none() {
+                                    span { class: "w-6 text-green-600", "✓" }
+                                } else {
+                                    span { class: "w-6 text-red-500", "✗" }
+                                }
+                                span { class: "w-72 text-gray-600", "{step.name}" }
+                                span { class: "w-20 text-gray-400", "{step.elapsed.as_millis()}ms" }
+                                span { class: "text-gray-500", "{step.detail}" }
+                            }
+                            if let Some(error) = &step.error {
+                                pre { class: "text-red-500 whitespace-pre-wrap text-sm ml-6", "{error}" }
+                            }
+                        }
+                    }
+                }
+                div { class: "flex justify-end mt-2",
+                    button {
+                        class: "mr-2 bg-sky-300 hover:bg-sky-500 text-white font-bold py-2 px-4 rounded",
+                        disabled: testing(),
+                        // 按编辑中的内容测试, 无需先保存
+                        onclick: move |_| {
+                            let Some(entry) = entries.read().get(current).cloned() else {
+                                return;
+                            };
+                            testing.set(true);
+                            diagnosis.set(Vec::new());
+                            spawn(async move {
+                                diagnosis.set(diagnose::diagnose(entry.source).await);
+                                testing.set(false);
+                            });
+                        },
+                        if testing() { "测试中..." } else { "测试连接" }
+                    }
+                    button {
+                        class: "bg-sky-500 hover:bg-sky-700 text-white font-bold py-2 px-4 rounded",
+                        onclick: {
+                            let on_saved = props.on_saved.clone();
+                            move |_| {
+                                let mut config = CONF.lock().unwrap().clone();
+                                config.sources = entries.read().iter().map(|entry| entry.source.clone()).collect();
+                                if let Err(err) = config.validate() {
+                                    error.set(format!("{:#}", err));
+                                    return;
+                                }
+                                let path = CONF_PATH.lock().unwrap().clone();
+                                if let Err(err) = config::save(&path, &entries.read()) {
+                                    error.set(format!("{:#}", err));
+                                    return;
+                                }
+                                // 保存后以新的顺序作为原始下标
+                                entries.with_mut(|entries| {
+                                    for (index, entry) in entries.iter_mut().enumerate() {
+                                        entry.origin = Some(index);
+                                    }
+                                });
+                                error.set(String::new());
+                                on_saved.call(config);
+                            }
+                        },
+                        "保存"
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn field(label: &'static str, value: String, mut on_input: impl FnMut(String) + 'static) -> Element {
+    rsx! {
+        div { class: "flex items-center mb-2",
+            label { class: "w-32 text-gray-600", "{label}" }
+            input {
+                class: "block bg-white w-full border border-slate-300 rounded-md py-1 px-2 focus:outline-none focus:border-sky-500",
+                r#type: if label.contains("password") || label.contains("passphrase") { "password" } else { "text" },
+                value: "{value}",
+                oninput: move |evt| on_input(evt.value())
+            }
+        }
+    }
+}
+
+fn protocol_field(entries: Signal<Vec<SourceEntry>>, current: usize, protocol: Protocol) -> Element {
+    let options = [
+        (Protocol::Native, "native", "native (需要 TDengine 客户端)"),
+        (Protocol::Ws, "ws", "ws (taosAdapter)"),
+        (Protocol::Wss, "wss", "wss (taosAdapter + TLS)"),
+    ];
+    rsx! {
+        div { class: "flex items-center mb-2",
+            label { class: "w-32 text-gray-600", "protocol" }
+            select {
+                class: "block bg-white w-full border border-slate-300 rounded-md py-1 px-2 focus:outline-none focus:border-sky-500",
+                onchange: move |evt: Event<FormData>| {
+                    let value = evt.value();
+                    edit(entries, current, move |s| {
+                        // native 是默认值, 不写入配置
+                        s.protocol = match value.as_str() {
+                            "ws" => Some(Protocol::Ws),
+                            "wss" => Some(Protocol::Wss),
+                            _ => None,
+                        };
+                    })
+                },
+                for (value , name , text) in options {
+                    option { value: "{name}", selected: value == protocol, "{text}" }
+                }
+            }
+        }
+    }
+}
+
+fn jump_fields(entries: Signal<Vec<SourceEntry>>, current: usize, hop: usize, jump: &JumpHost) -> Element {
+    rsx! {
+        div { class: "border rounded-md p-2 mb-2",
+            div { class: "flex mb-2",
+                p { class: "text-gray-600", "第 {hop + 1} 跳" }
+                button {
+                    class: "ml-auto bg-red-500 hover:bg-red-700 text-white py-1 px-2 rounded",
+                    onclick: move |_| edit(entries, current, move |s| {
+                        s.jump.remove(hop);
+                    }),
+                    "删除"
+                }
+            }
+            {field("jump ip", jump.ip.clone(), move |v| edit_jump(entries, current, hop, move |j| j.ip = v.trim().to_string()))}
+            {field("jump port", jump.port.map(|p| p.to_string()).unwrap_or_default(), move |v| edit_jump(entries, current, hop, move |j| j.port = optional(v).map(|p| p.parse().unwrap_or(0))))}
+            {field("jump user", jump.user.clone(), move |v| edit_jump(entries, current, hop, move |j| j.user = v.trim().to_string()))}
+            {field("jump password", jump.password.as_ref().map(Secret::to_text).unwrap_or_default(), move |v| edit_jump(entries, current, hop, move |j| j.password = Secret::from_text(v)))}
+            {field("jump key", jump.key.as_ref().map(|p| p.display().to_string()).unwrap_or_default(), move |v| edit_jump(entries, current, hop, move |j| j.key = optional(v).map(PathBuf::from)))}
+            {field("jump key_passphrase", jump.key_passphrase.as_ref().map(Secret::to_text).unwrap_or_default(), move |v| edit_jump(entries, current, hop, move |j| j.key_passphrase = Secret::from_text(v)))}
+        }
+    }
+}
+
+fn edit_jump(entries: Signal<Vec<SourceEntry>>, index: usize, hop: usize, f: impl FnOnce(&mut JumpHost)) {
+    edit(entries, index, move |source| {
+        if let Some(jump) = source.jump.get_mut(hop) {
+            f(jump);
+        }
+    });
+}
+
+fn edit(mut entries: Signal<Vec<SourceEntry>>, index: usize, f: impl FnOnce(&mut Source)) {
+    entries.with_mut(|entries| {
+        if let Some(entry) = entries.get_mut(index) {
+            f(&mut entry.source);
+        }
+    });
+}
+
+fn optional(value: String) -> Option<String> {
+    let value = value.trim().to_string();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
@@ -3,17 +3,24 @@ use std::ops::IndexMut;
 use dioxus::prelude::Signal;
 use dioxus::signals::Writable;
 
-use crate::{CURRENT_STABLE, get_rows, PAGE, PAGE_SIZE, ROBOT_ID, TableData};
+use crate::{apply_config, begin_connect, cancel_query, connect_host, current_host, CURRENT_STABLE, finish_connect, get_rows, get_stables, log, next_query, PAGE, PAGE_SIZE, QueryState, select_database, supervise, TableData, FILTER, turn_taos};
+use crate::config::Config;
 
 type UT = Signal<TableData>;
 
 pub enum Message {
     ChangeStable(String, i64, UT),
-    StableFilter(String, UT),
+    StableFilter(crate::td::Filter, UT),
+    TagFilter(Vec<crate::td::TagFilter>, UT),
     PrevPage(UT),
     NextPage(UT),
     Resizing(i64, i64, i64, UT),
     ResizeOver(UT),
+    SelectHost(String, i64, UT),
+    SelectDatabase(String, i64, UT),
+    ReloadHosts(Config, i64, UT),
+    CheckTunnels(i64, UT),
+    CancelQuery,
 }
 
 pub fn cal_widths(
@@ -23,6 +30,10 @@ pub fn cal_widths(
     real_moving_widths: Vec<i64>,
 ) -> Vec<i64> {
     let mut widths: Vec<i64> = Vec::new();
+    // 没有列时不需要计算
+    if size == 0 {
+        return widths;
+    }
     let each_width = width / size as i64;
     for _ in 0..size {
         widths.push(each_width);
@@ -47,14 +58,86 @@ pub fn cal_widths(
     widths
 }
 
+// 查询失败时只更新状态, 保留上一次的数据
+fn keep_rows_on_failure(table_data_state: &mut UT, query: &QueryState) -> bool {
+    if !matches!(query, QueryState::Failed(_)) {
+        return false;
+    }
+    table_data_state.with_mut(|data| data.query = query.clone());
+    true
+}
+
 pub async fn message_handler(msg: Message) {
     match msg {
+        Message::SelectHost(name, size, mut table_data_state) => {
+            turn_taos(name.clone());
+            // 未连接的主机在后台连接, 先显示连接中
+            if let Some((source, db)) = begin_connect(&name) {
+                table_data_state.with_mut(|_| {});
+                let result = connect_host(source.clone(), db).await;
+                finish_connect(&name, &source, result);
+            }
+            // 连接期间切换了主机时不再加载
+            if current_host() != name {
+                return;
+            }
+            // 当前超表不属于该主机时切换到第一个超表, 没有超表时为空
+            let stables = get_stables();
+            let mut stable = CURRENT_STABLE.lock().unwrap().get_mut().clone();
+            if !stables.contains(&stable) {
+                stable = stables.first().cloned().unwrap_or_default();
+            }
+            Box::pin(message_handler(Message::ChangeStable(stable, size, table_data_state))).await;
+        }
+        Message::SelectDatabase(db, size, table_data_state) => {
+            // 失败时超表列表显示原因, 超表已清空, 下面会清空旧库的数据
+            if let Err(err) = select_database(db).await {
+                log::info(format!("switch database failed: {:#}", err));
+            }
+            // 新库的超表列表, 从第一个超表开始浏览
+            let stable = get_stables().first().cloned().unwrap_or_default();
+            Box::pin(message_handler(Message::ChangeStable(stable, size, table_data_state))).await;
+        }
+        Message::ReloadHosts(config, size, mut table_data_state) => {
+            if apply_config(config) {
+                Box::pin(message_handler(Message::SelectHost(current_host(), size, table_data_state))).await;
+            } else {
+                // 当前主机未受影响, 保持当前视图, 只刷新主机列表
+                table_data_state.with_mut(|_| {});
+            }
+        }
+        Message::CheckTunnels(size, mut table_data_state) => match supervise().await {
+            None => {}
+            Some(true) => {
+                let stable = CURRENT_STABLE.lock().unwrap().get_mut().clone();
+                Box::pin(message_handler(Message::ChangeStable(stable, size, table_data_state))).await;
+            }
+            // 只刷新主机列表中的状态
+            Some(false) => table_data_state.with_mut(|_| {}),
+        },
+        Message::CancelQuery => cancel_query(),
         Message::ChangeStable(stable, size, mut table_data_state) => {
             PAGE.lock().unwrap().set(1);
+            // 过滤条件只对原来的表有效, 时间范围保留
+            if *CURRENT_STABLE.lock().unwrap().get_mut() != stable {
+                let range = FILTER.lock().unwrap().get_mut().range.clone();
+                FILTER.lock().unwrap().set(crate::td::Filter {
+                    range,
+                    ..Default::default()
+                });
+            }
             CURRENT_STABLE.lock().unwrap().set(stable);
+            let token = next_query();
             let start = std::time::Instant::now();
+            table_data_state.with_mut(|data| data.query = QueryState::Running);
 
-            let (rows, total_size, headers) = get_rows().await;
+            // 等待期间发起了新的查询时丢弃本次结果
+            let Some((rows, total_size, headers, query)) = get_rows(token).await else {
+                return;
+            };
+            if keep_rows_on_failure(&mut table_data_state, &query) {
+                return;
+            }
             table_data_state.with_mut(|data| {
                 let l = headers.len();
                 let total_page: i64;
@@ -72,6 +155,7 @@ pub async fn message_handler(msg: Message) {
                     data.real_moving_size,
                     data.widths,
                     data.spend,
+                    data.query,
                 ) = (
                     headers,
                     rows,
@@ -81,15 +165,23 @@ pub async fn message_handler(msg: Message) {
                     vec![0; l],
                     cal_widths(size, l as i64, vec![0; l], vec![0; l]),
                     start.elapsed().as_millis().to_string(),
+                    query,
                 )
             });
         }
 
-        Message::StableFilter(search_robot_id, mut table_data_state) => {
+        Message::StableFilter(filter, mut table_data_state) => {
             PAGE.lock().unwrap().set(1);
-            ROBOT_ID.lock().unwrap().set(search_robot_id);
+            FILTER.lock().unwrap().set(filter);
+            let token = next_query();
             let start = std::time::Instant::now();
-            let (rows, total_size, headers) = get_rows().await;
+            table_data_state.with_mut(|data| data.query = QueryState::Running);
+            let Some((rows, total_size, headers, query)) = get_rows(token).await else {
+                return;
+            };
+            if keep_rows_on_failure(&mut table_data_state, &query) {
+                return;
+            }
 
             table_data_state.with_mut(|data| {
                 let total_page: i64;
@@ -104,15 +196,24 @@ pub async fn message_handler(msg: Message) {
                     data.total_page,
                     data.total_size,
                     data.spend,
+                    data.query,
                 ) = (
                     headers,
                     rows,
                     total_page,
                     total_size,
                     start.elapsed().as_millis().to_string(),
+                    query,
                 );
             });
         }
+        Message::TagFilter(tags, table_data_state) => {
+            let filter = crate::td::Filter {
+                tags,
+                ..FILTER.lock().unwrap().get_mut().clone()
+            };
+            Box::pin(message_handler(Message::StableFilter(filter, table_data_state))).await;
+        }
         Message::PrevPage(mut table_data_state) => {
             let mut page = PAGE.lock().unwrap().get();
             if page - 1 < 1 {
@@ -121,8 +222,15 @@ pub async fn message_handler(msg: Message) {
                 page -= 1;
             }
             PAGE.lock().unwrap().set(page);
+            let token = next_query();
             let start = std::time::Instant::now();
-            let (rows, total_size, headers) = get_rows().await;
+            table_data_state.with_mut(|data| data.query = QueryState::Running);
+            let Some((rows, total_size, headers, query)) = get_rows(token).await else {
+                return;
+            };
+            if keep_rows_on_failure(&mut table_data_state, &query) {
+                return;
+            }
             table_data_state.with_mut(|data| {
                 let total_page: i64;
                 if total_size / PAGE_SIZE == 0 && total_size > PAGE_SIZE {
@@ -136,20 +244,29 @@ pub async fn message_handler(msg: Message) {
                     data.total_page,
                     data.total_size,
                     data.spend,
+                    data.query,
                 ) = (
                     headers,
                     rows,
                     total_page,
                     total_size,
                     start.elapsed().as_millis().to_string(),
+                    query,
                 );
             });
         }
         Message::NextPage(mut table_data_state) => {
             let page = PAGE.lock().unwrap().get();
             PAGE.lock().unwrap().set(page + 1);
+            let token = next_query();
             let start = std::time::Instant::now();
-            let (rows, total_size, headers) = get_rows().await;
+            table_data_state.with_mut(|data| data.query = QueryState::Running);
+            let Some((rows, total_size, headers, query)) = get_rows(token).await else {
+                return;
+            };
+            if keep_rows_on_failure(&mut table_data_state, &query) {
+                return;
+            }
             table_data_state.with_mut(|data| {
                 let total_page: i64;
                 if total_size / PAGE_SIZE == 0 && total_size > PAGE_SIZE {
@@ -163,12 +280,14 @@ pub async fn message_handler(msg: Message) {
                     data.total_page,
                     data.total_size,
                     data.spend,
+                    data.query,
                 ) = (
                     headers,
                     rows,
                     total_page,
                     total_size,
                     start.elapsed().as_millis().to_string(),
+                    query,
                 );
             });
         }
@@ -1,9 +1,599 @@
-use anyhow::{Context, Result};
-use chrono::{DateTime, Local};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
 use taos::*;
 use taos::BorrowedValue::BigInt;
+use tokio::sync::Notify;
+
+use crate::{log, PAGE_SIZE};
+
+mod sql;
+
+// 显示时区: 本机时区、Asia/Shanghai 等 IANA 时区或固定偏移
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Zone {
+    Local,
+    Named(Tz),
+    Fixed(FixedOffset),
+}
+
+// 时间戳的显示方式, 按数据源的 timezone、locale 和 timestamp_format 配置
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeDisplay {
+    zone: Zone,
+    // 日期部分的格式, 由 locale 决定
+    date_format: &'static str,
+    // 配置的完整格式, 不配置时按精度显示毫秒、微秒或纳秒
+    format: Option<String>,
+}
+
+impl Default for TimeDisplay {
+    fn default() -> TimeDisplay {
+        TimeDisplay {
+            zone: Zone::Local,
+            date_format: "%Y-%m-%d",
+            format: None,
+        }
+    }
+}
+
+impl TimeDisplay {
+    pub fn new(timezone: Option<&str>, locale: Option<&str>, format: Option<&str>) -> Result<TimeDisplay> {
+        let mut display = TimeDisplay::default();
+        if let Some(timezone) = timezone {
+            display.zone = parse_timezone(timezone)?;
+        }
+        if let Some(locale) = locale {
+            display.date_format = date_format(locale)?;
+        }
+        if let Some(format) = format {
+            if format.trim().is_empty() || StrftimeItems::new(format).any(|item| item == Item::Error) {
+                bail!("timestamp_format {:?} is not a valid strftime format", format);
+            }
+            display.format = Some(format.to_string());
+        }
+        Ok(display)
+    }
+
+    pub fn format(&self, ts: &taos_query::common::Timestamp) -> String {
+        let format = match &self.format {
+            Some(format) => format.clone(),
+            None => {
+                let fraction = match ts.precision() {
+                    Precision::Millisecond => "%.3f",
+                    Precision::Microsecond => "%.6f",
+                    Precision::Nanosecond => "%.9f",
+                };
+                format!("{} %H:%M:%S{}", self.date_format, fraction)
+            }
+        };
+        let utc = ts.to_naive_datetime();
+        // IANA 时区按每个时间戳当时的偏移显示, 跨夏令时也正确
+        match self.zone {
+            Zone::Local => Local.from_utc_datetime(&utc).format(&format).to_string(),
+            Zone::Named(tz) => tz.from_utc_datetime(&utc).format(&format).to_string(),
+            Zone::Fixed(offset) => offset.from_utc_datetime(&utc).format(&format).to_string(),
+        }
+    }
+
+    // 把按显示时区填写的时间转为带偏移的时间, 服务端按偏移解析
+    fn to_server(&self, local: NaiveDateTime) -> Result<String> {
+        let format = "%Y-%m-%dT%H:%M:%S%.3f%z";
+        let time = match self.zone {
+            Zone::Local => Local.from_local_datetime(&local).single().map(|t| t.format(format).to_string()),
+            Zone::Named(tz) => tz.from_local_datetime(&local).single().map(|t| t.format(format).to_string()),
+            Zone::Fixed(offset) => offset.from_local_datetime(&local).single().map(|t| t.format(format).to_string()),
+        };
+        time.with_context(|| format!("{} does not exist or is ambiguous in this timezone", local))
+    }
+}
+
+// 时间范围, 起止为绝对时间或 now-2h 形式的相对时间, 为空时不限制
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TimeRange {
+    pub start: String,
+    pub end: String,
+}
+
+impl TimeRange {
+    // 最近一段时间, 如 15m、24h、7d
+    pub fn last(duration: &str) -> TimeRange {
+        TimeRange {
+            start: format!("now-{}", duration),
+            end: String::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start.trim().is_empty() && self.end.trim().is_empty()
+    }
+
+    // 检查起止时间能否解析
+    pub fn check(&self) -> Result<()> {
+        self.conditions("ts", &TimeDisplay::default()).map(|_| ())
+    }
+
+    fn conditions(&self, column: &str, display: &TimeDisplay) -> Result<Vec<String>> {
+        let column = sql::ident(column)?;
+        let mut conditions = Vec::new();
+        if let Some(start) = time_bound(&self.start, display).context("invalid start time")? {
+            conditions.push(format!("{} >= {}", column, start));
+        }
+        if let Some(end) = time_bound(&self.end, display).context("invalid end time")? {
+            conditions.push(format!("{} <= {}", column, end));
+        }
+        Ok(conditions)
+    }
+}
+
+// now、now-2h 原样交给服务端计算, 绝对时间按显示时区转换
+fn time_bound(expression: &str, display: &TimeDisplay) -> Result<Option<String>> {
+    let expression = expression.trim();
+    if expression.is_empty() {
+        return Ok(None);
+    }
+    if let Some(rest) = expression.strip_prefix("now").or_else(|| expression.strip_prefix("NOW")) {
+        let rest = rest.replace(' ', "");
+        if rest.is_empty() {
+            return Ok(Some("now".to_string()));
+        }
+        let sign = rest.get(..1).unwrap_or_default();
+        let amount = rest[sign.len()..].trim_end_matches(|c: char| c.is_ascii_alphabetic());
+        let unit = &rest[sign.len() + amount.len()..];
+        if !["+", "-"].contains(&sign)
+            || amount.is_empty()
+            || !amount.bytes().all(|b| b.is_ascii_digit())
+            || unit.len() != 1
+            || !"buasmhdw".contains(unit)
+        {
+            bail!("{:?} should look like now-2h, units are b u a s m h d w", expression);
+        }
+        return Ok(Some(format!("now {} {}{}", sign, amount, unit)));
+    }
+    // 日期时间选择器的值带 T
+    let text = expression.replace('T', " ");
+    let local = ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(&text, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(&text, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+        .with_context(|| format!("{:?} should look like 2024-01-01 14:00 or now-2h", expression))?;
+    Ok(Some(sql::string(&display.to_server(local)?)?))
+}
+
+// local 为本机时区, 其次按 IANA 时区名解析, 其余为 UTC 或 +08:00 形式的固定偏移
+fn parse_timezone(timezone: &str) -> Result<Zone> {
+    let value = timezone.trim();
+    if value.eq_ignore_ascii_case("local") {
+        return Ok(Zone::Local);
+    }
+    if let Ok(tz) = value.parse::<Tz>() {
+        return Ok(Zone::Named(tz));
+    }
+    let offset = value
+        .strip_prefix("UTC")
+        .or_else(|| value.strip_prefix("GMT"))
+        .unwrap_or(value);
+    let invalid = || {
+        format!(
+            "timezone {} is not supported, use local, a name like Asia/Shanghai or an offset like +08:00",
+            timezone
+        )
+    };
+    if offset.is_empty() || offset == "Z" {
+        return FixedOffset::east_opt(0).map(Zone::Fixed).with_context(invalid);
+    }
+    let (sign, rest) = match offset.split_at(1) {
+        ("+", rest) => (1, rest),
+        ("-", rest) => (-1, rest),
+        _ => bail!(invalid()),
+    };
+    let (hours, minutes) = match rest.split_once(':') {
+        Some((hours, minutes)) => (hours, minutes),
+        None if rest.len() == 4 => rest.split_at(2),
+        None => (rest, "0"),
+    };
+    let hours = hours.parse::<i32>().ok().filter(|h| *h <= 14).with_context(invalid)?;
+    let minutes = minutes.parse::<i32>().ok().filter(|m| *m < 60).with_context(invalid)?;
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+        .map(Zone::Fixed)
+        .with_context(invalid)
+}
+
+// 各地区习惯的日期写法
+fn date_format(locale: &str) -> Result<&'static str> {
+    let locale = locale.trim().replace('-', "_");
+    let language = locale.split('_').next().unwrap_or_default();
+    Ok(match (locale.as_str(), language) {
+        ("zh_TW" | "zh_HK", _) | (_, "ja") => "%Y/%m/%d",
+        (_, "zh" | "ko" | "sv") => "%Y-%m-%d",
+        ("en_US", _) => "%m/%d/%Y",
+        (_, "en" | "fr" | "es" | "it" | "pt") => "%d/%m/%Y",
+        (_, "de" | "ru" | "pl") => "%d.%m.%Y",
+        _ => bail!(
+            "locale {} is not supported, use one of zh_CN, zh_TW, ja_JP, ko_KR, en_US, en_GB, fr_FR, de_DE, ru_RU",
+            locale
+        ),
+    })
+}
+
+// 查询被取消或超时, 此时服务端的查询已被终止
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interrupted {
+    // bool 为是否终止了服务端的查询, 为 false 时只是本地停止等待
+    Cancelled(bool),
+    TimedOut(Duration, bool),
+}
+
+impl std::fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Interrupted::Cancelled(true) => write!(f, "query cancelled"),
+            Interrupted::Cancelled(false) => write!(f, "query cancelled locally only"),
+            Interrupted::TimedOut(timeout, true) => write!(f, "query timed out after {}s", timeout.as_secs()),
+            Interrupted::TimedOut(timeout, false) => {
+                write!(f, "query timed out after {}s, cancelled locally only", timeout.as_secs())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+// 查询的超时时间和取消信号
+#[derive(Clone, Default)]
+pub struct Control {
+    pub timeout: Option<Duration>,
+    pub cancel: Arc<Notify>,
+}
+
+// 服务端版本, 连接时检测, 元数据查询和界面功能按版本选择
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Server {
+    pub version: String,
+    pub major: u32,
+}
+
+// 服务端支持的功能, 不支持的功能在界面上关闭
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Features {
+    // 3.x 的元数据在 information_schema 中, 2.x 只能用 show
+    pub information_schema: bool,
+    // 3.x 从 ins_tags 读取子表标签值, 2.x 需要扫描超表
+    pub ins_tags: bool,
+    // 2.x 没有表注释
+    pub table_comment: bool,
+    // 2.x 查询超表时只能按 ts 排序
+    pub order_by_any: bool,
+}
+
+impl Server {
+    pub async fn detect(taos: &Taos) -> Result<Server> {
+        let version = taos.server_version().await?.to_string();
+        let major = version
+            .trim()
+            .split('.')
+            .next()
+            .and_then(|major| major.parse::<u32>().ok())
+            .with_context(|| format!("unknown server version {}", version))?;
+        Ok(Server { version, major })
+    }
+
+    // 获取所有数据库及其参数, 不含 3.x 的系统库
+    pub async fn databases(&self, taos: &Taos) -> Result<Vec<Database>> {
+        let sql = if self.features().information_schema {
+            "select * from information_schema.ins_databases"
+        } else {
+            "show databases"
+        };
+        let mut databases = named_rows(taos, sql.to_string())
+            .await?
+            .into_iter()
+            .map(|row| {
+                let get = |name: &str| row.get(name).cloned().unwrap_or_default();
+                Database {
+                    name: get("name"),
+                    precision: get("precision"),
+                    // 2.0 的列名为 keep0,keep1,keep2
+                    keep: row
+                        .iter()
+                        .find(|(name, _)| name.starts_with("keep"))
+                        .map(|(_, value)| value.clone())
+                        .unwrap_or_default(),
+                    vgroups: get("vgroups"),
+                    replica: get("replica"),
+                }
+            })
+            .filter(|db| !SYSTEM_DATABASES.contains(&db.name.as_str()))
+            .collect::<Vec<Database>>();
+        databases.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(databases)
+    }
+
+    pub fn features(&self) -> Features {
+        Features {
+            information_schema: self.major >= 3,
+            ins_tags: self.major >= 3,
+            table_comment: self.major >= 3,
+            order_by_any: self.major >= 3,
+        }
+    }
+}
+
+const SYSTEM_DATABASES: &[&str] = &["information_schema", "performance_schema"];
+
+// 数据库及其主要参数, 参数按服务端返回的文本显示
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Database {
+    pub name: String,
+    pub precision: String,
+    pub keep: String,
+    pub vgroups: String,
+    pub replica: String,
+}
+
+// 表的列或标签, kind 为服务端返回的类型名
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+    pub kind: String,
+    pub tag: bool,
+}
+
+impl Column {
+    // 字符串和时间类型的值需要加引号
+    fn quoted(&self) -> bool {
+        let kind = self.kind.to_ascii_uppercase();
+        ["BINARY", "VARCHAR", "NCHAR", "JSON", "TIMESTAMP", "GEOMETRY", "VARBINARY"]
+            .iter()
+            .any(|prefix| kind.starts_with(prefix))
+    }
+
+    // 按类型生成值, 非字符串类型的值必须是数字或布尔值, 时间按显示时区转换
+    fn literal(&self, value: &str, display: &TimeDisplay) -> Result<String> {
+        if self.kind.eq_ignore_ascii_case("TIMESTAMP") {
+            // 纯数字为时间戳数值, 其余与时间范围相同
+            if let Ok(number) = value.trim().parse::<i64>() {
+                return Ok(number.to_string());
+            }
+            return time_bound(value, display)
+                .with_context(|| format!("{} is TIMESTAMP", self.name))?
+                .with_context(|| format!("{} needs a time", self.name));
+        }
+        if self.quoted() {
+            sql::string(value)
+        } else if self.kind.eq_ignore_ascii_case("BOOL") {
+            sql::boolean(value)
+        } else {
+            sql::number(value).with_context(|| format!("{} is {}", self.name, self.kind))
+        }
+    }
+
+    // 按列类型可用的运算符
+    pub fn operators(&self) -> Vec<Operator> {
+        use Operator::*;
+        let kind = self.kind.to_ascii_uppercase();
+        if kind == "BOOL" {
+            vec![Eq, Ne, IsNull, IsNotNull]
+        } else if kind == "TIMESTAMP" {
+            vec![Eq, Ne, Lt, Gt, Between, IsNull, IsNotNull]
+        } else if self.quoted() {
+            vec![Eq, Ne, Like, In, IsNull, IsNotNull]
+        } else {
+            vec![Eq, Ne, Lt, Gt, Between, In, IsNull, IsNotNull]
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Between,
+    In,
+    Like,
+    IsNull,
+    IsNotNull,
+}
+
+impl Operator {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Operator::Eq => "=",
+            Operator::Ne => "!=",
+            Operator::Lt => "<",
+            Operator::Gt => ">",
+            Operator::Between => "between",
+            Operator::In => "in",
+            Operator::Like => "like",
+            Operator::IsNull => "is null",
+            Operator::IsNotNull => "is not null",
+        }
+    }
+
+    // 需要填写的值的个数, in 的多个值用逗号分隔填在一个输入框中
+    pub fn arity(&self) -> usize {
+        match self {
+            Operator::IsNull | Operator::IsNotNull => 0,
+            Operator::Between => 2,
+            _ => 1,
+        }
+    }
+}
+
+// 一个过滤条件, values 的个数与运算符对应, in 时为所有候选值
+#[derive(Debug, Clone, PartialEq)]
+pub struct Predicate {
+    pub column: Column,
+    pub operator: Operator,
+    pub values: Vec<String>,
+}
+
+impl Predicate {
+    // 生成条件, 值不符合列类型时返回错误
+    pub fn condition(&self, display: &TimeDisplay) -> Result<String> {
+        let name = sql::ident(&self.column.name)?;
+        let value = |index: usize| {
+            self.column
+                .literal(self.values.get(index).map(String::as_str).unwrap_or_default(), display)
+        };
+        Ok(match self.operator {
+            Operator::Between => format!("{} between {} and {}", name, value(0)?, value(1)?),
+            Operator::In => {
+                let values = self
+                    .values
+                    .iter()
+                    .map(|value| self.column.literal(value, display))
+                    .collect::<Result<Vec<String>>>()?;
+                format!("{} in ({})", name, values.join(", "))
+            }
+            // like 的模式总是字符串
+            Operator::Like => format!(
+                "{} like {}",
+                name,
+                sql::string(self.values.first().map(String::as_str).unwrap_or_default())?
+            ),
+            Operator::IsNull | Operator::IsNotNull => format!("{} {}", name, self.operator.label()),
+            operator => format!("{} {} {}", name, operator.label(), value(0)?),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Join {
+    #[default]
+    And,
+    Or,
+}
+
+impl Join {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Join::And => "and",
+            Join::Or => "or",
+        }
+    }
+}
+
+// 按标签值过滤, 同一标签的多个值为或, 不同标签之间为且
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagFilter {
+    pub tag: Column,
+    pub values: Vec<String>,
+}
+
+impl TagFilter {
+    fn condition(&self, display: &TimeDisplay) -> Result<String> {
+        let values = self
+            .values
+            .iter()
+            .map(|value| self.tag.literal(value, display))
+            .collect::<Result<Vec<String>>>()?;
+        Ok(format!("{} in ({})", sql::ident(&self.tag.name)?, values.join(", ")))
+    }
+}
+
+// 数据的排序, 默认按 ts 倒序
+#[derive(Debug, Clone, PartialEq)]
+pub struct Order {
+    pub column: String,
+    pub descending: bool,
+}
+
+impl Default for Order {
+    fn default() -> Order {
+        Order {
+            column: "ts".to_string(),
+            descending: true,
+        }
+    }
+}
+
+impl Order {
+    // 点击同一列时切换方向, 换列时先升序
+    pub fn toggle(&self, column: &str) -> Order {
+        Order {
+            column: column.to_string(),
+            descending: self.column == column && !self.descending,
+        }
+    }
+
+    fn clause(&self) -> Result<String> {
+        let direction = if self.descending { "desc" } else { "asc" };
+        Ok(format!("order by {} {}", sql::ident(&self.column)?, direction))
+    }
+
+    // 服务端不支持按其他列排序时退回按 ts 排序
+    fn supported(&self, features: Features) -> Order {
+        if features.order_by_any || self.column == "ts" {
+            self.clone()
+        } else {
+            Order::default()
+        }
+    }
+}
+
+// 数据查询的过滤条件, 条件之间按 join 组合, 再与标签过滤取且
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Filter {
+    pub join: Join,
+    pub predicates: Vec<Predicate>,
+    pub tags: Vec<TagFilter>,
+    // 按 ts 列限制的时间范围
+    pub range: TimeRange,
+    pub order: Order,
+}
 
-use crate::PAGE_SIZE;
+impl Filter {
+    fn where_clause(&self, display: &TimeDisplay) -> Result<String> {
+        let mut conditions = self.range.conditions("ts", display)?;
+        conditions.extend(
+            self.tags
+                .iter()
+                .map(|tag| tag.condition(display))
+                .collect::<Result<Vec<String>>>()?,
+        );
+        if !self.predicates.is_empty() {
+            let predicates = self
+                .predicates
+                .iter()
+                .map(|predicate| Ok(format!("({})", predicate.condition(display)?)))
+                .collect::<Result<Vec<String>>>()?;
+            conditions.push(format!("({})", predicates.join(&format!(" {} ", self.join.label()))));
+        }
+        if conditions.is_empty() {
+            Ok("".to_string())
+        } else {
+            Ok(format!("where {}", conditions.join(" and ")))
+        }
+    }
+}
+
+// 超表的标签和每个子表的标签值, 值与 tags 的顺序一致
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TagTable {
+    pub tags: Vec<Column>,
+    pub tables: Vec<(String, Vec<String>)>,
+}
+
+// 表结构, headers 为 describe 返回的列名, 新版本会多出 encode、compress 等列
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Schema {
+    pub headers: Vec<String>,
+    pub columns: Vec<Vec<String>>,
+    pub tags: Vec<Vec<String>>,
+    pub comment: Option<String>,
+    pub ddl: String,
+}
 
 #[derive(Debug, serde::Deserialize)]
 pub struct Status {
@@ -14,67 +604,284 @@ pub struct Status {
 
 #[derive(Debug, serde::Deserialize)]
 pub struct STable {
+    pub db: String,
     pub stable_name: String,
 }
 
 impl STable {
-    pub fn new(stable_name: String) -> STable {
-        STable { stable_name }
+    pub fn new(db: String, stable_name: String) -> STable {
+        STable { db, stable_name }
+    }
+
+    // 带库名并转义的表名
+    fn table(&self) -> Result<String> {
+        sql::table(&self.db, &self.stable_name)
     }
+
     // 获取所有超表
-    pub async fn get_stables(taos: &Taos) -> Result<Vec<STable>> {
-        let mut stables = taos
-            .query("show stables")
+    pub async fn get_stables(taos: &Taos, server: &Server, db: &str) -> Result<Vec<STable>> {
+        let sql = if server.features().information_schema {
+            format!(
+                "select stable_name from information_schema.ins_stables where db_name = {}",
+                sql::string(db)?
+            )
+        } else {
+            // 2.x 的 show stables 返回 name 列
+            format!("show {}.stables", sql::ident(db)?)
+        };
+        let mut stables = first_column(taos, sql)
             .await?
-            .deserialize()
-            .try_collect::<Vec<STable>>()
-            .await?;
+            .into_iter()
+            .map(|stable_name| STable::new(db.to_string(), stable_name))
+            .collect::<Vec<STable>>();
 
         stables.sort_by(|a, b| a.stable_name.cmp(&b.stable_name));
 
         Ok(stables)
     }
 
-    // 获取超表下的子表
-    #[allow(unused)]
-    pub async fn get_sub_tables(&self, taos: &Taos) -> Result<Vec<Table>> {
-        let name = self
-            .stable_name
-            .split('_')
-            .nth(1)
-            .context("error stable name")?;
-        let sql = format!("{}_%", name);
-        let mut sub_tables = taos
-            .query(format!("show tables like \"{}\"", sql))
+    // 获取超表下的子表, 最多 limit 个
+    pub async fn get_sub_tables(&self, taos: &Taos, server: &Server, limit: usize) -> Result<Vec<Table>> {
+        let sql = if server.features().information_schema {
+            format!(
+                "select table_name from information_schema.ins_tables where db_name = {} and stable_name = {} limit {}",
+                sql::string(&self.db)?,
+                sql::string(&self.stable_name)?,
+                limit
+            )
+        } else {
+            // 2.x 查询超表的 tbname 时每个子表返回一行
+            format!("select tbname from {} limit {}", self.table()?, limit)
+        };
+        let mut sub_tables = first_column(taos, sql)
             .await?
-            .deserialize()
-            .try_collect::<Vec<Table>>()
-            .await?;
+            .into_iter()
+            .map(|table_name| Table { table_name })
+            .collect::<Vec<Table>>();
         sub_tables.sort_by(|a, b| a.table_name.cmp(&b.table_name));
 
         Ok(sub_tables)
     }
 
-    // 获取超表下的数据
+    // 在库中按名称搜索子表, 返回 (超表, 子表), 最多 limit 个
+    pub async fn search_sub_tables(
+        taos: &Taos,
+        server: &Server,
+        db: &str,
+        keyword: &str,
+        limit: usize,
+    ) -> Result<Vec<(String, Table)>> {
+        let sql = if server.features().information_schema {
+            format!(
+                "select stable_name, table_name from information_schema.ins_tables where db_name = {} and type = 'CHILD_TABLE' and table_name like {} limit {}",
+                sql::string(db)?,
+                sql::contains(keyword)?,
+                limit
+            )
+        } else {
+            // 2.x 的 show tables 不支持 limit, 普通表的 stable_name 为空
+            format!("show {}.tables like {}", sql::ident(db)?, sql::contains(keyword)?)
+        };
+        let (fields, rows) = field_rows(taos, &sql).await?;
+        let stable_index = column_index(&fields, &["stable_name"])?;
+        let table_index = column_index(&fields, &["table_name"])?;
+        let mut found = rows
+            .into_iter()
+            .filter(|row| !row[stable_index].is_empty())
+            .take(limit)
+            .map(|row| {
+                let table_name = row[table_index].clone();
+                (row[stable_index].clone(), Table { table_name })
+            })
+            .collect::<Vec<(String, Table)>>();
+        found.sort_by(|a, b| (&a.0, &a.1.table_name).cmp(&(&b.0, &b.1.table_name)));
+
+        Ok(found)
+    }
+
+    // 获取表的列和标签
+    pub async fn get_columns(&self, taos: &Taos) -> Result<Vec<Column>> {
+        // describe 的第 4 列为 TAG 时是标签, 2.x 和 3.x 相同
+        let columns = value_rows(taos, format!("describe {}", self.table()?))
+            .await?
+            .into_iter()
+            .map(|row| Column {
+                name: row[0].clone(),
+                kind: row[1].clone(),
+                tag: row.get(3).is_some_and(|note| note.eq_ignore_ascii_case("TAG")),
+            })
+            .collect::<Vec<Column>>();
+        Ok(columns)
+    }
+
+    // 获取超表的标签和所有子表的标签值
+    pub async fn get_tags(&self, taos: &Taos, server: &Server) -> Result<TagTable> {
+        let tags = self
+            .get_columns(taos)
+            .await?
+            .into_iter()
+            .filter(|column| column.tag)
+            .collect::<Vec<Column>>();
+        if tags.is_empty() {
+            return Ok(TagTable::default());
+        }
+
+        let mut tables = if server.features().ins_tags {
+            // ins_tags 每个子表的每个标签一行
+            let sql = format!(
+                "select table_name, tag_name, tag_value from information_schema.ins_tags where db_name = {} and stable_name = {}",
+                sql::string(&self.db)?,
+                sql::string(&self.stable_name)?
+            );
+            let mut tables = std::collections::BTreeMap::<String, Vec<String>>::new();
+            for row in value_rows(taos, sql).await? {
+                let values = tables
+                    .entry(row[0].clone())
+                    .or_insert_with(|| vec![String::new(); tags.len()]);
+                if let Some(index) = tags.iter().position(|tag| tag.name == row[1]) {
+                    values[index] = row[2].clone();
+                }
+            }
+            tables.into_iter().collect::<Vec<(String, Vec<String>)>>()
+        } else {
+            // 2.x 只查询 tbname 和标签时每个子表返回一行
+            let names = tags
+                .iter()
+                .map(|tag| sql::ident(&tag.name))
+                .collect::<Result<Vec<String>>>()?;
+            let sql = format!("select tbname, {} from {}", names.join(", "), self.table()?);
+            value_rows(taos, sql)
+                .await?
+                .into_iter()
+                .map(|mut row| {
+                    let values = row.split_off(1);
+                    (row.remove(0), values)
+                })
+                .collect::<Vec<(String, Vec<String>)>>()
+        };
+        tables.sort_by(|a, b| a.0.cmp(&b.0));
+
+        Ok(TagTable { tags, tables })
+    }
+
+    // 获取表结构和建表语句, stable 为 false 时按子表或普通表查询
+    pub async fn get_schema(&self, taos: &Taos, server: &Server, stable: bool) -> Result<Schema> {
+        let mut result = taos.query(format!("describe {}", self.table()?)).await?;
+        let headers = result
+            .fields()
+            .iter()
+            .map(|v| v.name().to_string())
+            .collect::<Vec<String>>();
+        let note = headers
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case("note"))
+            .context("describe returned no note")?;
+        let mut schema = Schema {
+            headers,
+            ..Default::default()
+        };
+        let mut rows = result.rows();
+        while let Some(row) = rows.try_next().await? {
+            let values = row
+                .map(|(_, value)| value.to_string())
+                .collect::<Result<Vec<String>, _>>()?;
+            if values[note].eq_ignore_ascii_case("TAG") {
+                schema.tags.push(values);
+            } else {
+                schema.columns.push(values);
+            }
+        }
+
+        let kind = if stable { "stable" } else { "table" };
+        // 第二列为建表语句
+        let ddl = value_rows(taos, format!("show create {} {}", kind, self.table()?)).await?;
+        schema.ddl = ddl
+            .first()
+            .and_then(|row| row.get(1))
+            .cloned()
+            .with_context(|| format!("show create {} returned nothing", kind))?;
+
+        // 表注释只在 3.x 的 information_schema 中
+        if server.features().table_comment {
+            let sql = if stable {
+                format!(
+                    "select remark from information_schema.ins_stables where db_name = {} and stable_name = {}",
+                    sql::string(&self.db)?,
+                    sql::string(&self.stable_name)?
+                )
+            } else {
+                format!(
+                    "select table_comment from information_schema.ins_tables where db_name = {} and table_name = {}",
+                    sql::string(&self.db)?,
+                    sql::string(&self.stable_name)?
+                )
+            };
+            schema.comment = first_column(taos, sql)
+                .await?
+                .into_iter()
+                .next()
+                .filter(|comment| !comment.is_empty());
+        }
+        Ok(schema)
+    }
+
+    // 获取超表下的数据, 总数和当前页分别从连接池取连接并行查询
     pub async fn get_rows(
         &self,
-        taos: &Taos,
+        pool: &TaosPool,
+        server: &Server,
         page: i32,
-        robot_id: Option<String>,
+        filter: &Filter,
+        display: &TimeDisplay,
+        control: &Control,
     ) -> Result<(Vec<Vec<String>>, Option<i64>)> {
         let offset = (page as i64 - 1) * PAGE_SIZE;
-        // robot_id
-        let robot_id_where = if let Some(robot_id) = robot_id {
-            format!("where robot_id like \"%{}%\"", robot_id)
-        } else {
-            "".to_string()
-        };
+        let table = self.table()?;
+        let where_clause = filter.where_clause(display)?;
 
         // 查询总的记录树
-        let count_sql = format!(
-            "select count(*) as c from {} {}",
-            self.stable_name, robot_id_where
+        let count_sql = format!("select count(*) as c from {} {}", table, where_clause);
+        let sql = format!(
+            "select * from {} {} {} limit {} offset {}",
+            table,
+            where_clause,
+            filter.order.supported(server.features()).clause()?,
+            PAGE_SIZE,
+            offset
         );
+
+        let queries = async { tokio::try_join!(Self::count(pool, &count_sql), Self::page(pool, &sql, display)) };
+        let deadline = async {
+            match control.timeout {
+                Some(timeout) => tokio::time::sleep(timeout).await,
+                None => std::future::pending().await,
+            }
+        };
+        // 取消或超时时只丢弃 future 不会停止服务端的查询, 需要 kill query
+        let cancelled = tokio::select! {
+            result = queries => {
+                let (total_size, list) = result?;
+                return Ok((list, Some(total_size)));
+            }
+            _ = control.cancel.notified() => true,
+            _ = deadline => false,
+        };
+        let killed = match kill_queries(pool, server, &[count_sql, sql]).await {
+            Ok(killed) => killed > 0,
+            Err(err) => {
+                log::info(format!("kill query failed: {:#}", err));
+                false
+            }
+        };
+        if cancelled {
+            Err(Interrupted::Cancelled(killed).into())
+        } else {
+            Err(Interrupted::TimedOut(control.timeout.unwrap_or_default(), killed).into())
+        }
+    }
+
+    async fn count(pool: &TaosPool, count_sql: &str) -> Result<i64> {
+        let taos = pool.get().await?;
         let mut count_result = taos.query(count_sql).await?;
         let mut total_size = 0;
         if let Some(row) = count_result.rows().try_next().await? {
@@ -84,11 +891,12 @@ impl STable {
                 }
             }
         };
+        Ok(total_size)
+    }
 
-        let sql = format!(
-            "select * from {} {} order by ts desc limit {} offset {}",
-            self.stable_name, robot_id_where, PAGE_SIZE, offset
-        );
+    // 查询一页数据, 第一行为列名
+    async fn page(pool: &TaosPool, sql: &str, display: &TimeDisplay) -> Result<Vec<Vec<String>>> {
+        let taos = pool.get().await?;
         let mut result = taos.query(sql).await?;
 
         let fields = result
@@ -104,12 +912,9 @@ impl STable {
             let mut data = Vec::new();
             for (_, value) in row {
                 match value {
+                    // 按结果集的精度显示小数秒
                     BorrowedValue::Timestamp(value) => {
-                        let ts = value
-                            .to_datetime_with_tz()
-                            .format("%Y-%m-%d %H:%M:%S")
-                            .to_string();
-                        data.push(ts);
+                        data.push(display.format(&value));
                     }
                     _ => {
                         data.push(value.to_string().unwrap());
@@ -118,9 +923,130 @@ impl STable {
             }
             list.push(data);
         }
+        Ok(list)
+    }
+}
+
+// 查询结果第一列的字符串值
+async fn first_column(taos: &Taos, sql: String) -> Result<Vec<String>> {
+    let mut result = taos.query(sql).await?;
+    let mut values = Vec::new();
+    let mut rows = result.rows();
+    while let Some(mut row) = rows.try_next().await? {
+        if let Some((_, value)) = row.next() {
+            values.push(value.to_string()?);
+        }
+    }
+    Ok(values)
+}
+
+// 按列名取每一行的字符串值
+async fn named_rows(taos: &Taos, sql: String) -> Result<Vec<std::collections::BTreeMap<String, String>>> {
+    let mut result = taos.query(sql).await?;
+    let mut values = Vec::new();
+    let mut rows = result.rows();
+    while let Some(row) = rows.try_next().await? {
+        let mut named = std::collections::BTreeMap::new();
+        for (field, value) in row {
+            named.insert(field.to_string(), value.to_string()?);
+        }
+        values.push(named);
+    }
+    Ok(values)
+}
+
+// 按列的顺序取每一行的字符串值
+async fn value_rows(taos: &Taos, sql: String) -> Result<Vec<Vec<String>>> {
+    let mut result = taos.query(sql).await?;
+    let mut values = Vec::new();
+    let mut rows = result.rows();
+    while let Some(row) = rows.try_next().await? {
+        values.push(
+            row.map(|(_, value)| value.to_string())
+                .collect::<Result<Vec<String>, _>>()?,
+        );
+    }
+    Ok(values)
+}
+
+// 查询结果的列名和每一行的字符串值
+async fn field_rows(taos: &Taos, sql: &str) -> Result<(Vec<String>, Vec<Vec<String>>)> {
+    let mut result = taos.query(sql).await?;
+    let fields = result
+        .fields()
+        .iter()
+        .map(|v| v.name().to_string())
+        .collect::<Vec<String>>();
+    let mut values = Vec::new();
+    let mut rows = result.rows();
+    while let Some(row) = rows.try_next().await? {
+        values.push(row.map(|(_, value)| value.to_string().unwrap_or_default()).collect());
+    }
+    Ok((fields, values))
+}
+
+fn column_index(fields: &[String], names: &[&str]) -> Result<usize> {
+    fields
+        .iter()
+        .position(|field| names.iter().any(|name| field.eq_ignore_ascii_case(name)))
+        .with_context(|| format!("result has no {} column", names.join(" or ")))
+}
+
+// 本进程建立的连接号, websocket 连接的 pid 是 taosAdapter 的, 此时为空
+fn own_connections(fields: &[String], rows: &[Vec<String>], pid: u32) -> Result<Vec<String>> {
+    let id_index = column_index(fields, &["conn_id", "connId", "id"])?;
+    let pid_index = column_index(fields, &["pid"])?;
+    Ok(rows
+        .iter()
+        .filter(|row| row[pid_index].trim() == pid.to_string())
+        .map(|row| row[id_index].trim().to_string())
+        .collect())
+}
 
-        Ok((list, Some(total_size)))
+// 从 show queries 中选出本进程连接上、sql 与本次查询相同的查询, 返回终止用的 id
+fn select_victims(fields: &[String], rows: &[Vec<String>], own_conn_ids: &[String], sqls: &[String]) -> Result<Vec<String>> {
+    let sql_index = column_index(fields, &["sql"])?;
+    // 3.x 有 kill_id 和 conn_id, 2.x 的 query_id 形如 连接号:查询号
+    let (id_index, conn_index) = match column_index(fields, &["kill_id"]) {
+        Ok(kill_index) => (kill_index, Some(column_index(fields, &["conn_id"])?)),
+        Err(_) => (column_index(fields, &["query_id"])?, None),
+    };
+    Ok(rows
+        .iter()
+        .filter(|row| {
+            let conn_id = match conn_index {
+                Some(index) => row[index].trim(),
+                None => row[id_index].split(':').next().unwrap_or_default().trim(),
+            };
+            // 服务端记录的 sql 可能被截断
+            let running = row[sql_index].trim();
+            own_conn_ids.iter().any(|own| own == conn_id)
+                && !running.is_empty()
+                && sqls.iter().any(|sql| sql.starts_with(running))
+        })
+        .map(|row| row[id_index].clone())
+        .collect())
+}
+
+// 终止本进程发出的查询, 返回终止的个数, 无法确定自己的连接时不终止任何查询
+async fn kill_queries(pool: &TaosPool, server: &Server, sqls: &[String]) -> Result<usize> {
+    let taos = pool.get().await?;
+    let (fields, rows) = field_rows(&taos, "show connections").await?;
+    let own = own_connections(&fields, &rows, std::process::id())?;
+    if own.is_empty() {
+        return Ok(0);
+    }
+    let (fields, rows) = field_rows(&taos, "show queries").await?;
+    let ids = select_victims(&fields, &rows, &own, sqls)?;
+    for id in ids.iter() {
+        let sql = if server.major >= 3 {
+            format!("kill query {}", sql::string(id)?)
+        } else {
+            format!("kill query {}", sql::query_id(id)?)
+        };
+        taos.exec(sql).await?;
     }
+    Ok(ids.len())
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -129,3 +1055,233 @@ pub struct Table {
 }
 
 impl Table {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str, kind: &str) -> Column {
+        Column {
+            name: name.to_string(),
+            kind: kind.to_string(),
+            tag: false,
+        }
+    }
+
+    fn predicate(column: Column, operator: Operator, values: &[&str]) -> Predicate {
+        Predicate {
+            column,
+            operator,
+            values: values.iter().map(|value| value.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn predicate_escapes_values() {
+        let robot_id = column("robot_id", "VARCHAR");
+        assert_eq!(
+            predicate(robot_id.clone(), Operator::Like, &["%' or 1=1 --%"]).condition(&TimeDisplay::default()).unwrap(),
+            r"`robot_id` like '%\' or 1=1 --%'"
+        );
+        assert_eq!(
+            predicate(robot_id, Operator::In, &["a", "b'); drop table t; --"]).condition(&TimeDisplay::default()).unwrap(),
+            r"`robot_id` in ('a', 'b\'); drop table t; --')"
+        );
+        // 时间只接受能解析的值
+        let ts = column("ts", "TIMESTAMP");
+        assert!(predicate(ts, Operator::Between, &["2024-01-01", "2024-01-02' or '1'='1"])
+            .condition(&TimeDisplay::default())
+            .is_err());
+    }
+
+    #[test]
+    fn timestamp_predicates_use_display_timezone() {
+        let display = TimeDisplay::new(Some("+08:00"), None, None).unwrap();
+        let ts = column("ts", "TIMESTAMP");
+        // 与时间范围一样按显示时区填写, 带偏移交给服务端
+        assert_eq!(
+            predicate(ts.clone(), Operator::Gt, &["2024-01-01 08:00"]).condition(&display).unwrap(),
+            "`ts` > '2024-01-01T08:00:00.000+0800'"
+        );
+        assert_eq!(
+            predicate(ts.clone(), Operator::Between, &["2024-01-01T00:00", "2024-01-02"]).condition(&display).unwrap(),
+            "`ts` between '2024-01-01T00:00:00.000+0800' and '2024-01-02T00:00:00.000+0800'"
+        );
+        assert_eq!(
+            Filter {
+                predicates: vec![predicate(ts.clone(), Operator::Eq, &["2024-01-01 08:00"])],
+                range: TimeRange {
+                    start: "2024-01-01 08:00".to_string(),
+                    end: String::new(),
+                },
+                ..Default::default()
+            }
+            .where_clause(&display)
+            .unwrap(),
+            "where `ts` >= '2024-01-01T08:00:00.000+0800' and ((`ts` = '2024-01-01T08:00:00.000+0800'))"
+        );
+        assert_eq!(predicate(ts.clone(), Operator::Lt, &["now-1h"]).condition(&display).unwrap(), "`ts` < now - 1h");
+        assert_eq!(predicate(ts.clone(), Operator::Eq, &["1704067200000"]).condition(&display).unwrap(), "`ts` = 1704067200000");
+        assert!(predicate(ts, Operator::Eq, &["yesterday"]).condition(&display).is_err());
+    }
+
+    #[test]
+    fn predicate_rejects_hostile_numbers_and_names() {
+        let status = column("status", "INT");
+        assert_eq!(predicate(status.clone(), Operator::Gt, &["3"]).condition(&TimeDisplay::default()).unwrap(), "`status` > 3");
+        assert!(predicate(status.clone(), Operator::Eq, &["1 or 1=1"]).condition(&TimeDisplay::default()).is_err());
+        assert!(predicate(status, Operator::In, &["1", "2) or (1=1"]).condition(&TimeDisplay::default()).is_err());
+        assert!(predicate(column("online", "BOOL"), Operator::Eq, &["true or 1=1"]).condition(&TimeDisplay::default()).is_err());
+        assert!(predicate(column("a` or 1=1 --", "INT"), Operator::IsNull, &[]).condition(&TimeDisplay::default()).is_err());
+    }
+
+    #[test]
+    fn where_clause_groups_predicates() {
+        let tag = Column {
+            tag: true,
+            ..column("site", "NCHAR(16)")
+        };
+        let filter = Filter {
+            join: Join::Or,
+            predicates: vec![
+                predicate(column("status", "INT"), Operator::Eq, &["1"]),
+                predicate(column("robot_id", "VARCHAR"), Operator::IsNull, &[]),
+            ],
+            tags: vec![TagFilter {
+                tag,
+                values: vec!["it's".to_string()],
+            }],
+            range: TimeRange::last("15m"),
+            ..Default::default()
+        };
+        assert_eq!(
+            filter.where_clause(&TimeDisplay::default()).unwrap(),
+            r"where `ts` >= now - 15m and `site` in ('it\'s') and ((`status` = 1) or (`robot_id` is null))"
+        );
+        assert_eq!(Filter::default().where_clause(&TimeDisplay::default()).unwrap(), "");
+    }
+
+    #[test]
+    fn timezone_names_follow_daylight_saving() {
+        let display = TimeDisplay::new(Some("Europe/Berlin"), None, None).unwrap();
+        let at = |text: &str| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap();
+        assert_eq!(display.to_server(at("2024-01-15 12:00")).unwrap(), "2024-01-15T12:00:00.000+0100");
+        assert_eq!(display.to_server(at("2024-07-15 12:00")).unwrap(), "2024-07-15T12:00:00.000+0200");
+        // 夏令时开始时跳过的时间不存在
+        assert!(display.to_server(at("2024-03-31 02:30")).is_err());
+
+        let display = TimeDisplay::new(Some("+08:00"), None, None).unwrap();
+        assert_eq!(display.to_server(at("2024-07-15 12:00")).unwrap(), "2024-07-15T12:00:00.000+0800");
+        assert!(TimeDisplay::new(Some("Mars/Olympus_Mons"), None, None).is_err());
+        assert!(TimeDisplay::new(Some("+25:00"), None, None).is_err());
+    }
+
+    #[test]
+    fn time_range_accepts_relative_and_absolute() {
+        let display = TimeDisplay::new(Some("+08:00"), None, None).unwrap();
+        let range = TimeRange {
+            start: "2024-01-01T14:00".to_string(),
+            end: "now - 2h".to_string(),
+        };
+        assert_eq!(
+            range.conditions("ts", &display).unwrap(),
+            vec!["`ts` >= '2024-01-01T14:00:00.000+0800'", "`ts` <= now - 2h"]
+        );
+        let range = TimeRange {
+            start: "2024-01-01".to_string(),
+            end: "now".to_string(),
+        };
+        assert_eq!(
+            range.conditions("ts", &TimeDisplay::new(Some("UTC"), None, None).unwrap()).unwrap(),
+            vec!["`ts` >= '2024-01-01T00:00:00.000+0000'", "`ts` <= now"]
+        );
+        assert!(TimeRange::default().conditions("ts", &display).unwrap().is_empty());
+    }
+
+    #[test]
+    fn time_range_rejects_hostile_input() {
+        for hostile in ["now-2h or 1=1", "now-2x", "now-", "now—2h", "now-2hh", "yesterday", "2024-01-01' or '1'='1", "now-1h; drop table t"] {
+            let range = TimeRange {
+                start: hostile.to_string(),
+                end: String::new(),
+            };
+            assert!(range.check().is_err(), "{}", hostile);
+        }
+    }
+
+    #[test]
+    fn order_toggles_and_quotes() {
+        let order = Order::default();
+        assert_eq!(order.clause().unwrap(), "order by `ts` desc");
+        let order = order.toggle("ts");
+        assert_eq!(order.clause().unwrap(), "order by `ts` asc");
+        let order = order.toggle("temperature");
+        assert_eq!(order.clause().unwrap(), "order by `temperature` asc");
+        assert_eq!(order.toggle("temperature").clause().unwrap(), "order by `temperature` desc");
+        assert!(order.toggle("ts` desc; drop table t; --").clause().is_err());
+
+        let v2 = Server { version: "2.6.0.99".to_string(), major: 2 }.features();
+        let v3 = Server { version: "3.0.4.1".to_string(), major: 3 }.features();
+        assert_eq!(order.supported(v2), Order::default());
+        assert_eq!(order.supported(v3), order);
+        assert_eq!(Order::default().toggle("ts").supported(v2), Order::default().toggle("ts"));
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn own_connections_match_pid() {
+        let fields = strings(&["conn_id", "user", "app", "pid", "end_point"]);
+        let rows = vec![
+            strings(&["11", "root", "tdengine-desktop", "4242", "127.0.0.1:6030"]),
+            strings(&["12", "root", "taos", "999", "10.0.0.2:6030"]),
+            strings(&["13", "root", "tdengine-desktop", "4242", "127.0.0.1:6030"]),
+        ];
+        assert_eq!(own_connections(&fields, &rows, 4242).unwrap(), strings(&["11", "13"]));
+        assert!(own_connections(&fields, &rows, 1).unwrap().is_empty());
+        // 2.x 的列名为 connId
+        let fields = strings(&["connId", "user", "ip:port", "pid"]);
+        let rows = vec![strings(&["7", "root", "127.0.0.1:50000", "4242"])];
+        assert_eq!(own_connections(&fields, &rows, 4242).unwrap(), strings(&["7"]));
+    }
+
+    #[test]
+    fn select_victims_only_on_own_connections() {
+        let sql = "select count(*) as c from `power`.`meters` ".to_string();
+        let sqls = vec![sql.clone(), "select * from `power`.`meters` order by `ts` desc limit 30 offset 0".to_string()];
+        let fields = strings(&["kill_id", "query_id", "conn_id", "app", "pid", "sql"]);
+        let rows = vec![
+            // 本进程的查询
+            strings(&["11:1", "1", "11", "tdengine-desktop", "4242", &sql]),
+            // 其他客户端的相同查询
+            strings(&["12:1", "1", "12", "taos", "999", &sql]),
+            // 本进程连接上被截断的查询
+            strings(&["13:2", "2", "13", "tdengine-desktop", "4242", "select * from `power`.`me"]),
+            // 本进程连接上的其他查询
+            strings(&["13:3", "3", "13", "tdengine-desktop", "4242", "select last(*) from `power`.`meters`"]),
+            strings(&["13:4", "4", "13", "tdengine-desktop", "4242", ""]),
+        ];
+        let own = strings(&["11", "13"]);
+        assert_eq!(select_victims(&fields, &rows, &own, &sqls).unwrap(), strings(&["11:1", "13:2"]));
+        assert!(select_victims(&fields, &rows, &[], &sqls).unwrap().is_empty());
+
+        // 2.x 从 query_id 中取连接号
+        let fields = strings(&["query_id", "user", "ip:port", "pid", "sql"]);
+        let rows = vec![
+            strings(&["7:1", "root", "127.0.0.1:50000", "4242", &sql]),
+            strings(&["8:1", "root", "10.0.0.2:50000", "999", &sql]),
+        ];
+        assert_eq!(select_victims(&fields, &rows, &strings(&["7"]), &sqls).unwrap(), strings(&["7:1"]));
+        assert!(select_victims(&strings(&["user"]), &rows, &own, &sqls).is_err());
+    }
+
+    #[test]
+    fn table_names_are_quoted() {
+        let stable = STable::new("power".to_string(), "meters".to_string());
+        assert_eq!(stable.table().unwrap(), "`power`.`meters`");
+        let hostile = STable::new("power".to_string(), "meters` where 1=1; --".to_string());
+        assert!(hostile.table().is_err());
+    }
+}
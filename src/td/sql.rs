@@ -0,0 +1,151 @@
+// 拼接 sql 时使用的转义, taos 的参数绑定只支持写入, 查询语句中的标识符和值都经这里生成
+use anyhow::{bail, Result};
+
+// 标识符用反引号包围, TDengine 的名称中不能出现反引号
+pub fn ident(name: &str) -> Result<String> {
+    if name.is_empty() {
+        bail!("name must not be empty");
+    }
+    if name.contains(['`', '\0']) {
+        bail!("name {:?} contains a backtick or NUL", name);
+    }
+    Ok(format!("`{}`", name))
+}
+
+// 库名.表名
+pub fn table(db: &str, table: &str) -> Result<String> {
+    Ok(format!("{}.{}", ident(db)?, ident(table)?))
+}
+
+// 字符串值用单引号包围, 转义反斜杠和单引号
+pub fn string(value: &str) -> Result<String> {
+    if value.contains('\0') {
+        bail!("value {:?} contains NUL", value);
+    }
+    Ok(format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'")))
+}
+
+// like 匹配包含 value 的字符串, value 中的通配符按原字符匹配
+pub fn contains(value: &str) -> Result<String> {
+    let escaped = value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    string(&format!("%{}%", escaped))
+}
+
+// 数值只允许整数或有限的小数
+pub fn number(value: &str) -> Result<String> {
+    let value = value.trim();
+    if value.parse::<i128>().is_ok() || value.parse::<f64>().is_ok_and(f64::is_finite) {
+        return Ok(value.to_string());
+    }
+    bail!("{:?} is not a number", value)
+}
+
+pub fn boolean(value: &str) -> Result<String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" | "1" => Ok("true".to_string()),
+        "false" | "0" => Ok("false".to_string()),
+        _ => bail!("{:?} is not true or false", value),
+    }
+}
+
+// 2.x 的查询号形如 连接号:查询号
+pub fn query_id(id: &str) -> Result<String> {
+    match id.split_once(':') {
+        Some((connection, query))
+            if [connection, query]
+                .iter()
+                .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit())) =>
+        {
+            Ok(id.to_string())
+        }
+        _ => bail!("{:?} is not a query id", id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ident_is_quoted() {
+        assert_eq!(ident("robot_id").unwrap(), "`robot_id`");
+        assert_eq!(ident("select").unwrap(), "`select`");
+        assert_eq!(ident("温度 t").unwrap(), "`温度 t`");
+        assert_eq!(table("power", "meters").unwrap(), "`power`.`meters`");
+    }
+
+    #[test]
+    fn ident_rejects_breaking_out() {
+        assert!(ident("").is_err());
+        assert!(ident("a` union select * from b --").is_err());
+        assert!(ident("a\0b").is_err());
+        assert!(table("db`; drop database db; --", "t").is_err());
+        assert!(table("db", "`t`").is_err());
+    }
+
+    #[test]
+    fn string_escapes_quotes() {
+        assert_eq!(string("r1").unwrap(), "'r1'");
+        assert_eq!(string("").unwrap(), "''");
+        assert_eq!(string("it's").unwrap(), r"'it\'s'");
+        assert_eq!(string("' or 1=1 --").unwrap(), r"'\' or 1=1 --'");
+        assert_eq!(string(r"a\").unwrap(), r"'a\\'");
+        // 末尾的反斜杠不能转义掉结束的引号
+        assert_eq!(string(r"\' or 1=1 --").unwrap(), r"'\\\' or 1=1 --'");
+        assert_eq!(string("\"%_").unwrap(), "'\"%_'");
+        assert!(string("a\0' or 1=1").is_err());
+    }
+
+    #[test]
+    fn string_stays_one_literal() {
+        for hostile in ["'", "''", r"\", r"\'", r"\\'", "'; drop table t; --", "a' and '1'='1"] {
+            let quoted = string(hostile).unwrap();
+            // 去掉首尾引号后, 每个引号前都有奇数个反斜杠
+            let inner = &quoted[1..quoted.len() - 1];
+            let mut backslashes = 0;
+            for c in inner.chars() {
+                if c == '\'' {
+                    assert_eq!(backslashes % 2, 1, "{} -> {}", hostile, quoted);
+                }
+                backslashes = if c == '\\' { backslashes + 1 } else { 0 };
+            }
+            assert_eq!(backslashes % 2, 0, "{} -> {}", hostile, quoted);
+        }
+    }
+
+    #[test]
+    fn contains_escapes_wildcards() {
+        assert_eq!(contains("d1").unwrap(), "'%d1%'");
+        assert_eq!(contains("d_1%").unwrap(), r"'%d\\_1\\%%'");
+        assert_eq!(contains("it's").unwrap(), r"'%it\'s%'");
+        assert!(contains("a\0").is_err());
+    }
+
+    #[test]
+    fn number_accepts_only_numbers() {
+        assert_eq!(number("42").unwrap(), "42");
+        assert_eq!(number(" -1.5 ").unwrap(), "-1.5");
+        assert_eq!(number("1e3").unwrap(), "1e3");
+        assert!(number("").is_err());
+        assert!(number("1 or 1=1").is_err());
+        assert!(number("1; drop table t").is_err());
+        assert!(number("0x10").is_err());
+        assert!(number("NaN").is_err());
+        assert!(number("inf").is_err());
+    }
+
+    #[test]
+    fn boolean_accepts_only_booleans() {
+        assert_eq!(boolean("TRUE").unwrap(), "true");
+        assert_eq!(boolean("0").unwrap(), "false");
+        assert!(boolean("true or 1=1").is_err());
+    }
+
+    #[test]
+    fn query_id_is_checked() {
+        assert_eq!(query_id("3:15").unwrap(), "3:15");
+        assert!(query_id("3").is_err());
+        assert!(query_id("3:").is_err());
+        assert!(query_id("3:1; drop database db").is_err());
+    }
+}
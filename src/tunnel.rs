@@ -0,0 +1,473 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use ssh2::{BlockDirections, Channel, CheckResult, ErrorCode, KnownHostFileKind, Session};
+
+use crate::proxy::Proxy;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// 转发空闲时等待的最长时间, 到时检查隧道是否已关闭
+const CLOSE_CHECK: Duration = Duration::from_millis(200);
+// 旧隧道的监听线程释放本地端口的最长等待时间
+const RELEASE_WAIT: Duration = Duration::from_secs(2);
+// libssh2 非阻塞模式下的 LIBSSH2_ERROR_EAGAIN
+const EAGAIN: ErrorCode = ErrorCode::Session(-37);
+
+pub enum SshAuth {
+    Password(String),
+    Key {
+        path: PathBuf,
+        passphrase: Option<String>,
+    },
+    // 未配置密码和私钥时使用 ssh-agent
+    Agent,
+}
+
+// 链路中的一台 ssh 主机
+pub struct Hop {
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+    pub auth: SshAuth,
+}
+
+pub struct TunnelConfig {
+    // 按顺序连接, 后一跳经前一跳转发, 最后一跳转发到 TDengine
+    pub hops: Vec<Hop>,
+    // 最后一跳上看到的 TDengine 端口
+    pub target_port: u16,
+    // 不指定时由系统分配空闲端口
+    pub local_port: Option<u16>,
+    // 经代理连接第一跳
+    pub proxy: Option<Proxy>,
+}
+
+// 建立隧道的步骤, 测试连接时逐步报告
+pub enum Stage<'a> {
+    // 解析第一跳的地址
+    Resolve(&'a Hop),
+    // 连接 ssh 端口, 后面的跳经前一跳试连
+    Connect(&'a Hop),
+    // ssh 握手、主机密钥校验和认证
+    Auth(&'a Hop),
+    // 经最后一跳转发到 TDengine 端口并监听本地端口
+    Forward(u16),
+}
+
+// 每一步的耗时和错误
+pub type Trace<'t> = dyn FnMut(Stage, Duration, Option<&anyhow::Error>) + 't;
+
+// 本地端口到远端 TDengine 的转发, drop 时关闭监听和所有转发连接
+pub struct Tunnel {
+    // 实际监听的本地端口
+    pub local_port: u16,
+    target_port: u16,
+    sessions: Vec<Session>,
+    listeners: Vec<JoinHandle<()>>,
+    closed: Arc<AtomicBool>,
+}
+
+impl Drop for Tunnel {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::SeqCst);
+        // drop 可能发生在界面线程且持有主机列表的锁, 在单独的线程中等监听线程退出再断开会话.
+        // 在同一端口上重建隧道时由 bind 等待端口释放
+        let listeners = std::mem::take(&mut self.listeners);
+        let sessions = std::mem::take(&mut self.sessions);
+        thread::spawn(move || {
+            for listener in listeners {
+                let _ = listener.join();
+            }
+            for session in sessions.iter().rev() {
+                let _ = session.disconnect(None, "tunnel closed", None);
+            }
+        });
+    }
+}
+
+impl Tunnel {
+    pub fn probe(&self) -> Probe {
+        Probe {
+            sessions: self.sessions.clone(),
+            target_port: self.target_port,
+        }
+    }
+}
+
+// 隧道健康检查, 可以拿到阻塞线程中执行
+pub struct Probe {
+    sessions: Vec<Session>,
+    target_port: u16,
+}
+
+impl Probe {
+    // 每一跳发送 keepalive, 再经最后一跳试开一个到 TDengine 的通道
+    pub fn check(&self) -> Result<()> {
+        for session in self.sessions.iter() {
+            retry(|| session.keepalive_send()).context("ssh keepalive failed")?;
+        }
+        let session = self.sessions.last().context("no ssh session")?;
+        let mut channel = retry(|| session.channel_direct_tcpip("127.0.0.1", self.target_port, None))
+            .with_context(|| format!("ssh tunnel cannot reach 127.0.0.1:{}", self.target_port))?;
+        let _ = retry(|| channel.close());
+        Ok(())
+    }
+}
+
+// 逐跳建立 ssh 会话并开始监听本地端口, 返回时隧道已可用
+pub fn open(config: TunnelConfig) -> Result<Tunnel> {
+    open_traced(config, &mut |_, _, _| {})
+}
+
+// 同 open, 每完成或失败一步都交给 trace
+pub fn open_traced(config: TunnelConfig, trace: &mut Trace) -> Result<Tunnel> {
+    // 先创建 Tunnel, 中途失败时 drop 会关闭已建立的中转
+    let closed = Arc::new(AtomicBool::new(false));
+    let mut tunnel = Tunnel {
+        local_port: 0,
+        target_port: config.target_port,
+        sessions: Vec::new(),
+        listeners: Vec::new(),
+        closed: closed.clone(),
+    };
+
+    let mut session: Option<Session> = None;
+    for (index, hop) in config.hops.iter().enumerate() {
+        let next = open_hop(&mut tunnel, session.take(), hop, config.proxy.as_ref(), trace)
+            .with_context(|| format!("ssh hop {} ({}@{}:{})", index + 1, hop.user, hop.host, hop.port))?;
+        tunnel.sessions.push(next.clone());
+        session = Some(next);
+    }
+    let session = session.context("no ssh host configured")?;
+    let last = config.hops.last().context("no ssh host configured")?;
+
+    let (address, listener) = step(trace, Stage::Forward(config.target_port), || {
+        // 先试开一个通道, 确认最后一跳能访问目标端口
+        probe(&session, "127.0.0.1", config.target_port).with_context(|| {
+            format!(
+                "ssh server {} cannot reach 127.0.0.1:{}",
+                last.host, config.target_port
+            )
+        })?;
+        relay(
+            session,
+            ("127.0.0.1".to_string(), config.target_port),
+            config.local_port.unwrap_or(0),
+            closed,
+        )
+    })?;
+    tunnel.local_port = address.port();
+    tunnel.listeners.push(listener);
+    Ok(tunnel)
+}
+
+// 连接并认证一跳, 第一跳直连, 后面的跳经前一跳的会话中转
+fn open_hop(
+    tunnel: &mut Tunnel,
+    prev: Option<Session>,
+    hop: &Hop,
+    proxy: Option<&Proxy>,
+    trace: &mut Trace,
+) -> Result<Session> {
+    let stream = match (prev, proxy) {
+        // 经代理连接时由代理解析主机名
+        (None, Some(proxy)) => step(trace, Stage::Connect(hop), || proxy.connect(&hop.host, hop.port))?,
+        (None, None) => {
+            let address = step(trace, Stage::Resolve(hop), || resolve(&hop.host, hop.port))?;
+            step(trace, Stage::Connect(hop), || {
+                TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)
+                    .with_context(|| format!("failed to connect to ssh server {}", address))
+            })?
+        }
+        (Some(prev), _) => {
+            step(trace, Stage::Connect(hop), || {
+                probe(&prev, &hop.host, hop.port)
+                    .with_context(|| format!("previous hop cannot reach {}:{}", hop.host, hop.port))
+            })?;
+            // 本地监听一个临时端口供新会话连接
+            let (address, listener) = relay(prev, (hop.host.clone(), hop.port), 0, tunnel.closed.clone())?;
+            tunnel.listeners.push(listener);
+            TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)
+                .with_context(|| format!("failed to connect to ssh relay {}", address))?
+        }
+    };
+    step(trace, Stage::Auth(hop), || login(hop, stream))
+}
+
+fn step<T>(trace: &mut Trace, stage: Stage, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    let start = Instant::now();
+    let result = f();
+    trace(stage, start.elapsed(), result.as_ref().err());
+    result
+}
+
+// 在阻塞的会话上试开一个通道, 确认能访问 host:port
+fn probe(session: &Session, host: &str, port: u16) -> Result<()> {
+    let mut channel = session.channel_direct_tcpip(host, port, None)?;
+    let _ = channel.close();
+    Ok(())
+}
+
+fn resolve(host: &str, port: u16) -> Result<SocketAddr> {
+    (host, port)
+        .to_socket_addrs()
+        .with_context(|| format!("failed to resolve ssh host {}", host))?
+        .next()
+        .with_context(|| format!("failed to resolve ssh host {}", host))
+}
+
+// 监听本地端口, 把每个连接经 session 转发到 target, 返回监听地址和监听线程
+fn relay(
+    session: Session,
+    target: (String, u16),
+    local_port: u16,
+    closed: Arc<AtomicBool>,
+) -> Result<(SocketAddr, JoinHandle<()>)> {
+    let listener = bind(local_port)?;
+    listener.set_nonblocking(true)?;
+    let address = listener.local_addr()?;
+
+    // 之后多个连接共用一个会话, 改为非阻塞轮询
+    session.set_blocking(false);
+    let listener = thread::spawn(move || accept(listener, session, target, closed));
+    Ok((address, listener))
+}
+
+// 监听本地端口. 固定端口可能还被刚关闭的旧隧道占用, 被占用时等一会再试
+fn bind(local_port: u16) -> Result<TcpListener> {
+    let deadline = Instant::now() + RELEASE_WAIT;
+    loop {
+        match TcpListener::bind(("127.0.0.1", local_port)) {
+            Err(err) if err.kind() == ErrorKind::AddrInUse && local_port != 0 && Instant::now() < deadline => {
+                thread::sleep(Duration::from_millis(50))
+            }
+            result => return result.with_context(|| format!("failed to listen on 127.0.0.1:{}", local_port)),
+        }
+    }
+}
+
+// 在已连接的 stream 上握手并认证一台 ssh 主机, stream 为直连或前一跳的本地中转
+fn login(hop: &Hop, stream: TcpStream) -> Result<Session> {
+    let mut session = Session::new()?;
+    session.set_tcp_stream(stream);
+    session.set_timeout(CONNECT_TIMEOUT.as_millis() as u32);
+    session.handshake().context("ssh handshake failed")?;
+    // 经中转连接时按真实主机名校验主机密钥
+    check_known_hosts(&session, &hop.host, hop.port)?;
+
+    let auth = match &hop.auth {
+        SshAuth::Password(password) => session.userauth_password(&hop.user, password),
+        SshAuth::Key { path, passphrase } => session.userauth_pubkey_file(
+            &hop.user,
+            None,
+            &expand_home(path),
+            passphrase.as_deref(),
+        ),
+        SshAuth::Agent => session.userauth_agent(&hop.user),
+    };
+    auth.context("ssh authentication failed")?;
+    if !session.authenticated() {
+        bail!("ssh authentication failed");
+    }
+
+    session.set_timeout(0);
+    session.set_keepalive(false, 30);
+    Ok(session)
+}
+
+// 只信任 ~/.ssh/known_hosts 中已有的主机密钥
+fn check_known_hosts(session: &Session, host: &str, port: u16) -> Result<()> {
+    let (key, _) = session.host_key().context("ssh server sent no host key")?;
+    let path = known_hosts_path()?;
+    let mut known_hosts = session.known_hosts()?;
+    if path.exists() {
+        known_hosts
+            .read_file(&path, KnownHostFileKind::OpenSSH)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+    }
+    match known_hosts.check_port(host, port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::NotFound => bail!(
+            "host key of {}:{} is not in {}, connect once with ssh to verify and add it",
+            host,
+            port,
+            path.display()
+        ),
+        CheckResult::Mismatch => bail!(
+            "host key of {}:{} does not match {}, the server may be impersonated",
+            host,
+            port,
+            path.display()
+        ),
+        CheckResult::Failure => bail!("failed to check host key of {}:{}", host, port),
+    }
+}
+
+fn known_hosts_path() -> Result<PathBuf> {
+    let home = std::env::var_os("HOME").context("HOME is not set")?;
+    Ok(PathBuf::from(home).join(".ssh").join("known_hosts"))
+}
+
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+// 一个会话上的所有转发都在监听线程中处理, 空闲时用 poll 等待监听端口、本地连接或 ssh 连接可读写.
+// 健康检查的 Probe 在另一个线程使用同一会话, ssh2 对会话的每次调用加锁, 两边的调用是串行的;
+// Probe 读走的数据会收进对应通道的缓冲, 最迟 CLOSE_CHECK 后由这里转发
+fn accept(listener: TcpListener, session: Session, target: (String, u16), closed: Arc<AtomicBool>) {
+    let mut links = Vec::<Link>::new();
+    let mut buf = [0u8; 16 * 1024];
+    while !closed.load(Ordering::SeqCst) {
+        loop {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    if let Ok(link) = Link::open(stream, &session, &target) {
+                        links.push(link);
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => return,
+            }
+        }
+        let mut moved = false;
+        links.retain_mut(|link| match link.pump(&mut buf) {
+            Ok(progress) => {
+                moved |= progress;
+                true
+            }
+            Err(_) => false,
+        });
+        // 读其他通道时可能已把数据收进了某个通道的缓冲, 这时不能等待
+        let buffered = links.iter().any(|link| link.channel.read_window().available > 0);
+        if !moved && !buffered {
+            wait(&listener, &links, &session);
+        }
+    }
+}
+
+// 一个本地连接和对应的 ssh 通道, 各方向最多暂存一块对端还没写完的数据
+struct Link {
+    stream: TcpStream,
+    channel: Channel,
+    to_channel: Vec<u8>,
+    to_stream: Vec<u8>,
+}
+
+impl Drop for Link {
+    fn drop(&mut self) {
+        let _ = self.channel.close();
+    }
+}
+
+impl Link {
+    fn open(stream: TcpStream, session: &Session, target: &(String, u16)) -> Result<Link> {
+        let channel = retry(|| session.channel_direct_tcpip(&target.0, target.1, None))?;
+        stream.set_nonblocking(true)?;
+        Ok(Link {
+            stream,
+            channel,
+            to_channel: Vec::new(),
+            to_stream: Vec::new(),
+        })
+    }
+
+    // 两个方向各尝试读写一次, 返回是否有数据移动, 任一端关闭时返回错误
+    fn pump(&mut self, buf: &mut [u8]) -> Result<bool> {
+        let mut moved = false;
+        if self.to_channel.is_empty() {
+            match self.stream.read(buf) {
+                Ok(0) => bail!("local connection closed"),
+                Ok(n) => self.to_channel.extend_from_slice(&buf[..n]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        if !self.to_channel.is_empty() {
+            match self.channel.write(&self.to_channel) {
+                Ok(n) => {
+                    self.to_channel.drain(..n);
+                    moved |= n > 0;
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        if self.to_stream.is_empty() {
+            match self.channel.read(buf) {
+                Ok(0) if self.channel.eof() => bail!("ssh channel closed"),
+                Ok(0) => {}
+                Ok(n) => self.to_stream.extend_from_slice(&buf[..n]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        if !self.to_stream.is_empty() {
+            match self.stream.write(&self.to_stream) {
+                Ok(0) => bail!("local connection closed"),
+                Ok(n) => {
+                    self.to_stream.drain(..n);
+                    moved = true;
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(moved)
+    }
+}
+
+// 等待任一连接就绪, 超时只用于检查隧道是否已关闭
+#[cfg(unix)]
+fn wait(listener: &TcpListener, links: &[Link], session: &Session) {
+    use std::os::unix::io::AsRawFd;
+
+    let poll = |fd, events| libc::pollfd { fd, events, revents: 0 };
+    // 会话总要关注入站数据, 写被阻塞时再关注可写
+    let outbound = matches!(session.block_directions(), BlockDirections::Outbound | BlockDirections::Both);
+    let mut fds = vec![
+        poll(listener.as_raw_fd(), libc::POLLIN),
+        poll(session.as_raw_fd(), if outbound { libc::POLLIN | libc::POLLOUT } else { libc::POLLIN }),
+    ];
+    for link in links {
+        let mut events = 0;
+        if link.to_channel.is_empty() {
+            events |= libc::POLLIN;
+        }
+        if !link.to_stream.is_empty() {
+            events |= libc::POLLOUT;
+        }
+        fds.push(poll(link.stream.as_raw_fd(), events));
+    }
+    unsafe {
+        libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, CLOSE_CHECK.as_millis() as libc::c_int);
+    }
+}
+
+// 没有 poll 的平台上短暂休眠
+#[cfg(not(unix))]
+fn wait(_listener: &TcpListener, _links: &[Link], _session: &Session) {
+    thread::sleep(Duration::from_millis(2));
+}
+
+// 非阻塞会话上的操作遇到 EAGAIN 时重试, 超时后返回错误
+fn retry<T>(mut f: impl FnMut() -> Result<T, ssh2::Error>) -> Result<T> {
+    let deadline = Instant::now() + CONNECT_TIMEOUT;
+    loop {
+        match f() {
+            Err(err) if err.code() == EAGAIN && Instant::now() < deadline => {
+                thread::sleep(Duration::from_millis(5))
+            }
+            result => return Ok(result?),
+        }
+    }
+}
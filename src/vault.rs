@@ -0,0 +1,573 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, bail, Context, Result};
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use dioxus::prelude::*;
+use lazy_static::lazy_static;
+
+use crate::config::{self, Config, PlainSecret, Secret, SourceEntry, CONF, CONF_PATH};
+
+// 密钥库文件格式: MAGIC | salt | nonce | 密文(json 格式的 名称 -> 密码)
+const MAGIC: &[u8] = b"TDVAULT1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+lazy_static! {
+    // 解锁后的密钥库, None 表示尚未解锁
+    pub static ref VAULT: Mutex<Option<Vault>> = Mutex::new(None);
+}
+
+pub struct Vault {
+    path: PathBuf,
+    salt: [u8; SALT_LEN],
+    key: [u8; 32],
+    secrets: BTreeMap<String, String>,
+}
+
+impl Vault {
+    // 新建空的密钥库并写入文件
+    pub fn create(path: &Path, passphrase: &str) -> Result<Vault> {
+        if passphrase.is_empty() {
+            bail!("master passphrase must not be empty");
+        }
+        if path.exists() {
+            bail!("vault file {} already exists", path.display());
+        }
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let vault = Vault {
+            path: path.to_path_buf(),
+            salt,
+            key: derive_key(passphrase, &salt)?,
+            secrets: BTreeMap::new(),
+        };
+        vault.save()?;
+        Ok(vault)
+    }
+
+    // 用主密码解密已有的密钥库
+    pub fn open(path: &Path, passphrase: &str) -> Result<Vault> {
+        let content = fs::read(path)
+            .with_context(|| format!("failed to read vault file {}", path.display()))?;
+        let header_len = MAGIC.len() + SALT_LEN;
+        if content.len() < header_len + NONCE_LEN || !content.starts_with(MAGIC) {
+            bail!("{} is not a vault file", path.display());
+        }
+        let mut salt = [0u8; SALT_LEN];
+        salt.copy_from_slice(&content[MAGIC.len()..header_len]);
+        let key = derive_key(passphrase, &salt)?;
+
+        let nonce = XNonce::from_slice(&content[header_len..header_len + NONCE_LEN]);
+        let plaintext = XChaCha20Poly1305::new(Key::from_slice(&key))
+            .decrypt(
+                nonce,
+                Payload {
+                    msg: &content[header_len + NONCE_LEN..],
+                    aad: &content[..header_len],
+                },
+            )
+            .map_err(|_| anyhow!("wrong master passphrase or corrupted vault file"))?;
+        let secrets = serde_json::from_slice(&plaintext).context("corrupted vault file")?;
+
+        Ok(Vault {
+            path: path.to_path_buf(),
+            salt,
+            key,
+            secrets,
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&String> {
+        self.secrets.get(name)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.secrets.keys().cloned().collect()
+    }
+
+    // 为迁移的密码选择不与已有密码及彼此重复的名称, 重复时加上 -2、-3 等后缀
+    pub fn plan(&self, secrets: &[PlainSecret]) -> Vec<PlainSecret> {
+        let mut taken = Vec::<String>::new();
+        secrets
+            .iter()
+            .map(|secret| {
+                let mut name = secret.name.clone();
+                let mut suffix = 2;
+                while self.secrets.contains_key(&name) || taken.contains(&name) {
+                    name = format!("{}-{}", secret.name, suffix);
+                    suffix += 1;
+                }
+                taken.push(name.clone());
+                PlainSecret {
+                    name,
+                    ..secret.clone()
+                }
+            })
+            .collect()
+    }
+
+    // 新增或轮换密码
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        if name.trim().is_empty() {
+            bail!("secret name must not be empty");
+        }
+        self.secrets.insert(name.trim().to_string(), value.to_string());
+        self.save()
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<()> {
+        self.secrets.remove(name);
+        self.save()
+    }
+
+    // 每次写入都使用新的 nonce
+    fn save(&self) -> Result<()> {
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&self.salt);
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let plaintext = serde_json::to_vec(&self.secrets)?;
+        let ciphertext = XChaCha20Poly1305::new(Key::from_slice(&self.key))
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: &plaintext,
+                    aad: &header,
+                },
+            )
+            .map_err(|_| anyhow!("failed to encrypt vault"))?;
+
+        let mut content = header;
+        content.extend_from_slice(&nonce);
+        content.extend_from_slice(&ciphertext);
+
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, content)
+            .with_context(|| format!("failed to write vault file {}", tmp.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&tmp, fs::Permissions::from_mode(0o600))?;
+        }
+        fs::rename(&tmp, &self.path)
+            .with_context(|| format!("failed to write vault file {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32]> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|err| anyhow!("failed to derive vault key: {}", err))?;
+    Ok(key)
+}
+
+pub fn is_unlocked() -> bool {
+    VAULT.lock().unwrap().is_some()
+}
+
+// 从已解锁的密钥库中读取密码
+pub fn get(name: &str) -> Result<String> {
+    let vault = VAULT.lock().unwrap();
+    let vault = vault.as_ref().context("vault is locked")?;
+    vault
+        .get(name)
+        .cloned()
+        .with_context(|| format!("secret {} not found in vault", name))
+}
+
+// 迁移时各明文密码在密钥库中的名称, 未解锁时为默认名称
+pub fn migration_plan(config: &Config) -> Vec<PlainSecret> {
+    let secrets = config.plaintext_secrets();
+    match VAULT.lock().unwrap().as_ref() {
+        Some(vault) => vault.plan(&secrets),
+        None => secrets,
+    }
+}
+
+// 将明文密码按 migration_plan 的名称移入密钥库, 并把配置改为引用密钥库
+pub fn migrate(config: &Config, secrets: &[PlainSecret]) -> Result<Config> {
+    let mut config = config.clone();
+    {
+        let mut vault = VAULT.lock().unwrap();
+        let vault = vault.as_mut().context("vault is locked")?;
+        // 展示名称之后密钥库有变化时不覆盖已有的密码
+        if let Some(secret) = secrets.iter().find(|secret| vault.get(&secret.name).is_some()) {
+            bail!("secret {} already exists in vault, review the migration again", secret.name);
+        }
+        for secret in secrets {
+            let source = config
+                .sources
+                .get_mut(secret.index)
+                .context("config changed during migration")?;
+            if let Some(slot) = source.secret_mut(&secret.field) {
+                if let Some(Secret::Plain(value)) = slot.clone() {
+                    vault.set(&secret.name, &value)?;
+                    *slot = Some(Secret::Vault {
+                        vault: secret.name.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    let entries = config
+        .sources
+        .iter()
+        .enumerate()
+        .map(|(index, source)| SourceEntry {
+            origin: Some(index),
+            source: source.clone(),
+        })
+        .collect::<Vec<SourceEntry>>();
+    let path = CONF_PATH.lock().unwrap().clone();
+    config::save(&path, &entries)?;
+    Ok(config)
+}
+
+#[derive(Props, Clone, PartialEq)]
+pub struct UnlockList {
+    pub on_unlocked: EventHandler,
+}
+
+// 输入主密码解锁密钥库, 密钥库文件不存在时新建
+#[allow(non_snake_case)]
+#[component]
+pub fn VaultUnlock(props: UnlockList) -> Element {
+    let mut passphrase = use_signal(String::new);
+    let mut confirm = use_signal(String::new);
+    let mut error = use_signal(String::new);
+    let path = CONF.lock().unwrap().vault_path();
+    let exists = path.exists();
+    let path_text = path.display().to_string();
+
+    rsx! {
+        div {
+            class: "p-4 flex flex-col items-center",
+            font_family: "hack",
+            div { class: "w-1/3",
+                p { class: "text-xl font-bold text-gray-600 mb-2",
+                    if exists { "解锁密钥库" } else { "新建密钥库" }
+                }
+                p { class: "text-gray-400 mb-2 break-all", "{path_text}" }
+                input {
+                    class: "block bg-white w-full border border-slate-300 rounded-md py-2 px-3 mb-2 focus:outline-none focus:border-sky-500",
+                    r#type: "password",
+                    placeholder: "主密码",
+                    oninput: move |evt| passphrase.set(evt.value())
+                }
+                if !exists {
+                    input {
+                        class: "block bg-white w-full border border-slate-300 rounded-md py-2 px-3 mb-2 focus:outline-none focus:border-sky-500",
+                        r#type: "password",
+                        placeholder: "确认主密码",
+                        oninput: move |evt| confirm.set(evt.value())
+                    }
+                }
+                if !error.read().is_empty() {
+                    p { class: "text-red-500 mb-2", "{error}" }
+                }
+                button {
+                    class: "bg-sky-500 hover:bg-sky-700 text-white font-bold py-2 px-4 rounded",
+                    onclick: {
+                        let on_unlocked = props.on_unlocked.clone();
+                        move |_| {
+                            let result = if exists {
+                                Vault::open(&path, &passphrase.read())
+                            } else if *passphrase.read() != *confirm.read() {
+                                Err(anyhow!("passphrases do not match"))
+                            } else {
+                                Vault::create(&path, &passphrase.read())
+                            };
+                            match result {
+                                Ok(vault) => {
+                                    *VAULT.lock().unwrap() = Some(vault);
+                                    on_unlocked.call(());
+                                }
+                                Err(err) => error.set(format!("{:#}", err)),
+                            }
+                        }
+                    },
+                    if exists { "解锁" } else { "创建" }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Props, Clone, PartialEq)]
+pub struct VaultList {
+    pub on_close: EventHandler,
+    pub on_config_changed: EventHandler<Config>,
+}
+
+// 密钥库管理: 新增、轮换、删除密码, 迁移配置中的明文密码
+#[allow(non_snake_case)]
+#[component]
+pub fn VaultManager(props: VaultList) -> Element {
+    let mut unlocked = use_signal(is_unlocked);
+    let mut name = use_signal(String::new);
+    let mut value = use_signal(String::new);
+    let mut error = use_signal(String::new);
+    // 密钥库内容不在 signal 中, 修改后借助它刷新界面
+    let mut version = use_signal(|| 0);
+
+    let _ = version();
+    let names = VAULT
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(Vault::names)
+        .unwrap_or_default();
+    let plaintext = migration_plan(&CONF.lock().unwrap());
+
+    rsx! {
+        div {
+            class: "fixed inset-0 bg-gray-500/50 flex justify-center items-center",
+            div {
+                class: "bg-white rounded-md shadow-lg p-4 flex flex-col",
+                style: "width:800px;height:600px",
+                div { class: "flex mb-2",
+                    p { class: "text-lg font-bold text-gray-600", "密钥库" }
+                    button {
+                        class: "ml-auto text-gray-400 hover:text-gray-600",
+                        onclick: {
+                            let on_close = props.on_close.clone();
+                            move |_| on_close.call(())
+                        },
+                        "关闭"
+                    }
+                }
+                if !unlocked() {
+                    VaultUnlock {
+                        props: UnlockList {
+                            on_unlocked: EventHandler::new(move |_| unlocked.set(true)),
+                        },
+                    }
+                } else {
+                    div { class: "flex-1 overflow-auto",
+                        div { class: "list-none border",
+                            for secret in names.iter() {
+                                div { class: "flex border-b p-2 text-gray-600",
+                                    span { class: "flex-1", "{secret}" }
+                                    button {
+                                        class: "bg-sky-500 hover:bg-sky-700 text-white py-1 px-2 rounded mr-1",
+                                        onclick: {
+                                            let secret = secret.clone();
+                                            move |_| name.set(secret.clone())
+                                        },
+                                        "轮换"
+                                    }
+                                    button {
+                                        class: "bg-red-500 hover:bg-red-700 text-white py-1 px-2 rounded",
+                                        onclick: {
+                                            let secret = secret.clone();
+                                            move |_| {
+                                                let referenced = CONF.lock().unwrap().sources.iter().any(|source| {
+                                                    source.secrets().iter().any(|(_, s)| {
+                                                        matches!(s, Some(Secret::Vault { vault }) if *vault == secret)
+                                                    })
+                                                });
+                                                if referenced {
+                                                    error.set(format!("secret {} is still referenced by a source", secret));
+                                                    return;
+                                                }
+                                                let result = VAULT.lock().unwrap().as_mut().map(|vault| vault.remove(&secret));
+                                                if let Some(Err(err)) = result {
+                                                    error.set(format!("{:#}", err));
+                                                }
+                                                version += 1;
+                                            }
+                                        },
+                                        "删除"
+                                    }
+                                }
+                            }
+                        }
+                        div { class: "flex mt-2",
+                            input {
+                                class: "bg-white border border-slate-300 rounded-md py-1 px-2 mr-1 focus:outline-none focus:border-sky-500",
+                                placeholder: "名称",
+                                value: "{name}",
+                                oninput: move |evt| name.set(evt.value())
+                            }
+                            input {
+                                class: "flex-1 bg-white border border-slate-300 rounded-md py-1 px-2 mr-1 focus:outline-none focus:border-sky-500",
+                                r#type: "password",
+                                placeholder: "密码",
+                                value: "{value}",
+                                oninput: move |evt| value.set(evt.value())
+                            }
+                            button {
+                                class: "bg-sky-500 hover:bg-sky-700 text-white py-1 px-2 rounded",
+                                onclick: move |_| {
+                                    let result = VAULT
+                                        .lock()
+                                        .unwrap()
+                                        .as_mut()
+                                        .map(|vault| vault.set(&name.read(), &value.read()));
+                                    match result {
+                                        Some(Err(err)) => error.set(format!("{:#}", err)),
+                                        _ => {
+                                            error.set(String::new());
+                                            name.set(String::new());
+                                            value.set(String::new());
+                                        }
+                                    }
+                                    version += 1;
+                                },
+                                "保存"
+                            }
+                        }
+                        if !plaintext.is_empty() {
+                            div { class: "mt-4",
+                                p { class: "text-amber-600 mb-1", "配置中的明文密码, 迁移后保存为右侧的名称" }
+                                for secret in plaintext.iter() {
+                                    div { class: "flex border-b p-2 text-gray-600",
+                                        span { class: "flex-1", "{secret.label} {secret.field}" }
+                                        span { class: "flex-1", "→ {secret.name}" }
+                                    }
+                                }
+                                button {
+                                    class: "mt-2 bg-amber-500 hover:bg-amber-700 text-white py-1 px-2 rounded",
+                                    onclick: {
+                                        let on_config_changed = props.on_config_changed.clone();
+                                        let plaintext = plaintext.clone();
+                                        move |_| {
+                                            let config = CONF.lock().unwrap().clone();
+                                            match migrate(&config, &plaintext) {
+                                                Ok(config) => {
+                                                    error.set(String::new());
+                                                    on_config_changed.call(config);
+                                                }
+                                                Err(err) => error.set(format!("{:#}", err)),
+                                            }
+                                            version += 1;
+                                        }
+                                    },
+                                    "迁移到密钥库"
+                                }
+                            }
+                        }
+                    }
+                }
+                if !error.read().is_empty() {
+                    p { class: "text-red-500 mt-2", "{error}" }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vault_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("tdengine-desktop-{}-{}.bin", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn plain(index: usize, name: &str) -> PlainSecret {
+        PlainSecret {
+            index,
+            label: name.to_string(),
+            field: "password".to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn create_and_open_round_trip() {
+        let path = vault_path("round-trip");
+        let mut vault = Vault::create(&path, "master").unwrap();
+        vault.set("a.password", "taosdata").unwrap();
+        vault.set(" b.token ", "token").unwrap();
+        assert!(Vault::create(&path, "master").is_err());
+
+        let vault = Vault::open(&path, "master").unwrap();
+        assert_eq!(vault.names(), ["a.password", "b.token"]);
+        assert_eq!(vault.get("a.password").map(String::as_str), Some("taosdata"));
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn wrong_passphrase_and_tampering_are_rejected() {
+        let path = vault_path("tamper");
+        Vault::create(&path, "master").unwrap().set("a.password", "taosdata").unwrap();
+        let err = Vault::open(&path, "other").err().unwrap();
+        assert!(err.to_string().contains("wrong master passphrase"));
+
+        // 改动密文的任一字节都无法通过认证
+        let mut content = fs::read(&path).unwrap();
+        let last = content.len() - 1;
+        content[last] ^= 1;
+        fs::write(&path, &content).unwrap();
+        assert!(Vault::open(&path, "master").is_err());
+
+        // 头部作为附加数据参与认证, 改动 salt 同样会被发现
+        content[last] ^= 1;
+        content[MAGIC.len()] ^= 1;
+        fs::write(&path, &content).unwrap();
+        assert!(Vault::open(&path, "master").is_err());
+
+        fs::write(&path, b"not a vault").unwrap();
+        let err = Vault::open(&path, "master").err().unwrap();
+        assert!(err.to_string().contains("is not a vault file"));
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn plan_adds_suffix_for_taken_names() {
+        let path = vault_path("plan");
+        let mut vault = Vault::create(&path, "master").unwrap();
+        vault.set("a.password", "old").unwrap();
+        vault.set("a.password-2", "old").unwrap();
+        let planned = vault.plan(&[plain(0, "a.password"), plain(1, "b.password"), plain(2, "b.password")]);
+        let names = planned.iter().map(|secret| secret.name.as_str()).collect::<Vec<&str>>();
+        assert_eq!(names, ["a.password-3", "b.password", "b.password-2"]);
+        assert_eq!(planned[2].index, 2);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn migrate_moves_plaintext_into_vault() {
+        let _lock = config::SAVE_LOCK.lock().unwrap();
+        let path = vault_path("migrate");
+        let conf_path = std::env::temp_dir().join(format!("tdengine-desktop-migrate-{}.toml", std::process::id()));
+        let text = "# 数据源\n[[sources]]\nname = \"a\"\nip = \"10.0.0.1\"\nport = 6030\ndb = \"power\"\npassword = \"taosdata\"\n\n[[sources]]\nname = \"b\"\nip = \"10.0.0.2\"\nport = 6030\ndb = \"power\"\n";
+        fs::write(&conf_path, text).unwrap();
+        *CONF_PATH.lock().unwrap() = conf_path.clone();
+        let config = toml::from_str::<Config>(text).unwrap();
+
+        let plan = migration_plan(&config);
+        assert!(migrate(&config, &plan).is_err());
+        *VAULT.lock().unwrap() = Some(Vault::create(&path, "master").unwrap());
+        let plan = migration_plan(&config);
+        assert_eq!(plan.len(), 1);
+        assert_eq!(plan[0].name, "a.password");
+
+        let migrated = migrate(&config, &plan).unwrap();
+        assert!(migrated.plaintext_secrets().is_empty());
+        assert!(migrated.sources[0].password == Some(Secret::Vault { vault: "a.password".to_string() }));
+        assert_eq!(get("a.password").unwrap(), "taosdata");
+        // 写回的配置只引用密钥库, 注释保留
+        let saved = fs::read_to_string(&conf_path).unwrap();
+        assert!(saved.starts_with("# 数据源\n"));
+        assert!(!saved.contains("taosdata"));
+        assert!(toml::from_str::<Config>(&saved).unwrap() == migrated);
+        // 同名密码已存在时不覆盖
+        assert!(migrate(&config, &plan).is_err());
+
+        *VAULT.lock().unwrap() = None;
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(&conf_path);
+    }
+}